@@ -1,45 +1,114 @@
+use std::collections::HashMap;
+
+use near_sdk::json_types::U128;
+
 use crate::*;
 
 pub mod v1;
+pub mod v2;
 pub use v1::*;
+pub use v2::*;
 
+/// Versioned account record. Records are migrated lazily: older variants are
+/// converted to the current layout when read and written back as
+/// [VAccount::Current] the next time the account is saved.
+///
+/// V1 records don't track locked balances. Those with resting orders get them
+/// rebuilt from the orderbooks when read, and are rewritten by
+/// `migrate_markets`; the rest have nothing locked.
 #[derive(BorshSerialize, BorshDeserialize)]
 pub enum VAccount {
     V1(AccountV1),
+    Current(Account),
 }
 
-impl From<VAccount> for AccountV1 {
+impl From<VAccount> for Account {
     fn from(v: VAccount) -> Self {
         match v {
-            VAccount::V1(a) => a,
+            VAccount::V1(a) => a.into(),
+            VAccount::Current(a) => a,
         }
     }
 }
 
-impl From<AccountV1> for VAccount {
-    fn from(a: AccountV1) -> Self {
-        Self::V1(a)
+impl VAccount {
+    /// Convert the record to the current layout. Older records were paid for
+    /// at the sizes assumed back then, and are granted grace for the rest.
+    pub fn into_account(self, account_id: &AccountId) -> Account {
+        let legacy = matches!(self, VAccount::V1(_));
+        let mut account: Account = self.into();
+        account.initialize_id(account_id.clone());
+        if legacy {
+            account.grant_storage_grace();
+        }
+        account
+    }
+}
+
+impl From<Account> for VAccount {
+    fn from(a: Account) -> Self {
+        Self::Current(a)
     }
 }
 
 impl Contract {
     /// Save the account. Panics if the account has insufficient storage balance.
-    pub fn internal_save_account(&mut self, account_id: &AccountId, account: AccountV1) {
+    ///
+    /// With `auto_storage`, NEAR is first moved between the account's trading
+    /// balance and storage balance to cover storage, and the movement is
+    /// logged in an `auto_storage` event.
+    pub fn internal_save_account(&mut self, account_id: &AccountId, account: Account) {
+        self.internal_write_account(account_id, account, true);
+    }
+
+    /// Save the account of a maker whose order was filled, or of an account
+    /// credited with a refund. Neither is chosen by the account at the time, so
+    /// the save never fails on the account's storage: any shortfall has to be
+    /// covered by the account's next save.
+    pub fn internal_save_maker_account(&mut self, account_id: &AccountId, account: Account) {
+        self.internal_write_account(account_id, account, false);
+    }
+
+    fn internal_write_account(
+        &mut self,
+        account_id: &AccountId,
+        mut account: Account,
+        assert_storage_covered: bool,
+    ) {
+        let auto_storage_moved = if account.auto_storage {
+            account.rebalance_auto_storage()
+        } else {
+            0
+        };
+        account.shrink_storage_grace();
         _assert!(
-            account.is_storage_covered(),
-            // since there are no near collection fields in AccountV1, it's OK
+            !assert_storage_covered || account.is_storage_covered(),
+            // since there are no near collection fields in Account, it's OK
             // to do this assertion before writing the account
             errors::INSUFFICIENT_STORAGE_BALANCE
         );
+        self.internal_apply_balance_deltas(account.take_balance_deltas());
         self.accounts.insert(account_id, &account.into());
+
+        if auto_storage_moved != 0 {
+            emit_dex_event(DexEventType::AutoStorage(AutoStorageEvent {
+                account_id: account_id.clone(),
+                amount: U128(auto_storage_moved.unsigned_abs()),
+                direction: if auto_storage_moved > 0 {
+                    AutoStorageDirection::ToStorage
+                } else {
+                    AutoStorageDirection::ToBalance
+                },
+            }));
+        }
     }
 
     pub fn internal_try_save_account(
         &mut self,
         account_id: &AccountId,
-        account: AccountV1,
+        account: Account,
     ) -> Result<(), ()> {
-        if !account.is_storage_covered() {
+        if !account.can_cover_storage() {
             Err(())
         } else {
             self.internal_save_account(account_id, account);
@@ -47,15 +116,74 @@ impl Contract {
         }
     }
 
-    pub fn internal_get_account(&self, account_id: &AccountId) -> Option<AccountV1> {
-        self.accounts.get(account_id).map(|a| {
-            let mut account: AccountV1 = a.into();
-            account.initialize_id(account_id.clone());
+    pub fn internal_get_account(&self, account_id: &AccountId) -> Option<Account> {
+        self.accounts
+            .get(account_id)
+            .map(|record| self.internal_convert_account(account_id, record))
+    }
+
+    /// Convert an account record to the current layout. V1 records don't
+    /// track locked balances, so those with open orders get them rebuilt from
+    /// the orderbooks.
+    fn internal_convert_account(&self, account_id: &AccountId, record: VAccount) -> Account {
+        let legacy = matches!(record, VAccount::V1(_));
+        let mut account = record.into_account(account_id);
+        if legacy && !account.open_order_market_ids().is_empty() {
+            let locked = self.internal_compute_locked_balances(&account);
+            account.set_locked_balances(locked);
+            account.grant_storage_grace();
+        }
+        account
+    }
+
+    /// Rewrite a V1 account in the current layout. Does nothing for other
+    /// accounts. The record is written as-is, and its growth is covered by
+    /// storage grace.
+    pub fn internal_migrate_account(&mut self, account_id: &AccountId) {
+        if let Some(record @ VAccount::V1(_)) = self.accounts.get(account_id) {
+            let account = self.internal_convert_account(account_id, record);
+            self.accounts.insert(account_id, &account.into());
+        }
+    }
+
+    /// Sum the balances locked in an account's open orders from the
+    /// orderbooks, by token key.
+    pub fn internal_compute_locked_balances(&self, account: &Account) -> HashMap<String, Balance> {
+        let mut locked: HashMap<String, Balance> = HashMap::new();
+        for market_id in account.open_order_market_ids() {
+            let market = match self.internal_get_market(&market_id) {
+                Some(m) => m,
+                None => continue,
+            };
+            for (order_id, _) in account.open_orders_iter(&market_id) {
+                if let Some(order) = market.orderbook.get_order(order_id) {
+                    let (amount, token) = market.locked_for_order(
+                        order.unwrap_side(),
+                        order.unwrap_price(),
+                        order.open_qty_lots,
+                    );
+                    *locked.entry(token.key()).or_default() += amount;
+                }
+            }
+        }
+        locked
+    }
+
+    /// Panic if an account's tracked locked balances don't match its open
+    /// orders.
+    pub fn internal_assert_locked_balances(&self, account: &Account) {
+        let expected = self.internal_compute_locked_balances(account);
+        _assert_eq!(
             account
-        })
+                .get_locked_balances()
+                .into_iter()
+                .collect::<HashMap<_, _>>(),
+            expected,
+            "Locked balances do not match open orders"
+        );
     }
 
-    pub fn internal_unwrap_account(&self, account_id: &AccountId) -> AccountV1 {
+    pub fn internal_unwrap_account(&self, account_id: &AccountId) -> Account {
         _expect!(
             self.internal_get_account(account_id),
             errors::ACCOUNT_NOT_FOUND
@@ -0,0 +1,513 @@
+use std::collections::HashMap;
+
+use near_sdk::{
+    borsh::{self, BorshDeserialize, BorshSerialize},
+    Timestamp,
+};
+use tonic_sdk::borsh_size::BorshSize;
+
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Account {
+    /// ID of this account, initialized at runtime after loading from trie for
+    /// convenience. Not directly serialized to trie.
+    #[borsh_skip]
+    pub id: Option<AccountId>,
+
+    /// Amounts of tokens and native NEAR deposited to this account.
+    balances: TokenBalancesMap,
+
+    /// A map of the account's open orders.
+    open_orders: OpenOrdersMap,
+
+    /// Amount of NEAR deposited for storage. This is distinct from NEAR
+    /// available for trading.
+    pub storage_balance: Balance,
+
+    /// Amounts of tokens locked in open orders. Not included in `balances`.
+    locked: TokenBalancesMap,
+
+    /// If true, storage shortfalls are covered from the NEAR trading balance
+    /// when the account is saved.
+    pub auto_storage: bool,
+
+    /// Part of `storage_balance` moved from the NEAR trading balance by
+    /// `auto_storage`. Only this part flows back once it's no longer needed.
+    pub auto_storage_balance: Balance,
+
+    /// Part of `storage_balance` paid from the registration pool. It can't be
+    /// withdrawn, and goes back to the pool when the account is unregistered.
+    pub sponsored_storage: Balance,
+
+    /// Bytes of storage the account uses but hasn't paid for. Granted to
+    /// accounts last saved before sizes were measured exactly, for their
+    /// shortfall at the time, and only ever shrinks.
+    storage_grace: StorageUsage,
+
+    /// Bytes of orderbook storage used by the account's open orders, as
+    /// measured when they were placed. Released in proportion as orders close.
+    orderbook_storage: StorageUsage,
+
+    /// Orderbook storage released by maker orders that were removed while
+    /// matching this account's order. Offsets the net storage change measured
+    /// when the market is saved. See [Account::charge_orderbook_storage].
+    #[borsh_skip]
+    orderbook_storage_released: StorageUsage,
+
+    /// Changes to balances since the account was loaded. Applied to the
+    /// contract's liability totals when the account is saved.
+    #[borsh_skip]
+    balance_deltas: BalanceDeltas,
+}
+
+impl Account {
+    impl_lazy_accessors_clone!(id, unwrap_id, initialize_id, AccountId);
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+struct TokenBalancesMap(HashMap<String, Balance>);
+
+impl BorshSize for TokenBalancesMap {
+    fn borsh_size(&self) -> StorageUsage {
+        size::LENGTH_PREFIX
+            + self
+                .0
+                .keys()
+                .map(|key| size::string(key) + size::BALANCE)
+                .sum::<StorageUsage>()
+    }
+}
+
+impl TokenBalancesMap {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Data structure representing an account's open orders. The structure is
+/// ```ignore
+/// {
+///   market id -> {
+///     order id -> (original order size, timestamp)
+///   }
+/// }
+/// ```
+#[derive(BorshSerialize, BorshDeserialize)]
+struct OpenOrdersMap(HashMap<MarketId, HashMap<OrderId, (LotBalance, Timestamp)>>);
+
+impl OpenOrdersMap {
+    /// Iterate over open orders, if any exist.
+    pub fn market_orders_iter(
+        &self,
+        market_id: &MarketId,
+    ) -> impl Iterator<Item = (OrderId, (LotBalance, Timestamp))> {
+        self.0
+            .get(market_id)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+    }
+}
+
+impl BorshSize for OpenOrdersMap {
+    fn borsh_size(&self) -> StorageUsage {
+        // this structure is a map of maps, { market id -> { order info } }
+        let n_market_ids = self.0.len() as u64;
+        let total_market_keys_size = n_market_ids * (size::MARKET_ID + size::LENGTH_PREFIX);
+
+        let total_orders_size =
+            self.n_orders() * (size::ORDER_ID + size::LOT_BALANCE + size::TIMESTAMP);
+
+        size::LENGTH_PREFIX + total_market_keys_size + total_orders_size
+    }
+}
+
+impl OpenOrdersMap {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn market_ids(&self) -> Vec<MarketId> {
+        self.0.keys().cloned().collect()
+    }
+
+    pub fn n_orders(&self) -> u64 {
+        self.0.values().map(|oids| oids.len() as u64).sum()
+    }
+}
+
+/// Size of the account's trie record, ie, the key, the [VAccount] value, and
+/// the per-record overhead. Assumes an account ID of maximum length if the ID
+/// isn't initialized.
+impl BorshSize for Account {
+    fn borsh_size(&self) -> StorageUsage {
+        let id_size = match &self.id {
+            Some(id) => size::string(id.as_str()),
+            None => size::LENGTH_PREFIX + size::MAX_ACCOUNT_ID_LEN,
+        };
+        let key_size = size::STORAGE_KEY_PREFIX + id_size;
+        let value_size = size::ENUM_TAG
+            + self.balances.borsh_size()
+            + self.open_orders.borsh_size()
+            + size::BALANCE // storage_balance
+            + self.locked.borsh_size()
+            + size::BOOL // auto_storage
+            + size::BALANCE // auto_storage_balance
+            + size::BALANCE // sponsored_storage
+            + size::STORAGE_USAGE // storage_grace
+            + size::STORAGE_USAGE; // orderbook_storage
+
+        size::DATA_RECORD_OVERHEAD + key_size + value_size
+    }
+}
+
+impl From<AccountV1> for Account {
+    fn from(a: AccountV1) -> Self {
+        Account {
+            id: None,
+            balances: TokenBalancesMap(a.balances),
+            open_orders: OpenOrdersMap(a.open_orders),
+            storage_balance: a.storage_balance,
+            locked: TokenBalancesMap(HashMap::new()),
+            auto_storage: false,
+            auto_storage_balance: 0,
+            sponsored_storage: 0,
+            storage_grace: 0,
+            orderbook_storage: 0,
+            orderbook_storage_released: 0,
+            balance_deltas: BalanceDeltas::default(),
+        }
+        .with_legacy_orderbook_storage()
+    }
+}
+
+impl Account {
+    pub fn new(_account_id: &AccountId) -> Self {
+        Account {
+            id: Some(_account_id.clone()),
+            balances: TokenBalancesMap(HashMap::new()),
+            open_orders: OpenOrdersMap(HashMap::new()),
+            storage_balance: 0,
+            locked: TokenBalancesMap(HashMap::new()),
+            auto_storage: false,
+            auto_storage_balance: 0,
+            sponsored_storage: 0,
+            storage_grace: 0,
+            orderbook_storage: 0,
+            orderbook_storage_released: 0,
+            balance_deltas: BalanceDeltas::default(),
+        }
+    }
+
+    /// Charge orders placed before orderbook storage was measured at the
+    /// size previously assumed for an order.
+    fn with_legacy_orderbook_storage(mut self) -> Self {
+        self.orderbook_storage = self.open_orders.n_orders() * size::LEGACY_OPEN_LIMIT_ORDER;
+        self
+    }
+
+    /// Deposit amount to the balance of given token.
+    pub fn deposit(&mut self, token: &TokenType, amount: Balance) {
+        self.balance_deltas.record_free(token, amount as i128);
+        let key = token.key();
+        if let Some(x) = self.balances.0.get(&key).cloned() {
+            self.balances.0.insert(key, amount + x);
+        } else {
+            self.balances.0.insert(key, amount);
+        }
+    }
+
+    /// Withdraw amount of `token` from the internal balance.
+    /// Panics if `amount` is bigger than the current balance.
+    pub fn withdraw(&mut self, token: &TokenType, amount: Balance) {
+        self.balance_deltas.record_free(token, -(amount as i128));
+        let key = token.key();
+        if let Some(x) = self.balances.0.get(&key).cloned() {
+            if x < amount {
+                env::panic_str(errors::INSUFFICIENT_BALANCE);
+            }
+            if x == amount {
+                self.balances.0.remove(&key);
+            } else {
+                self.balances.0.insert(key, x - amount);
+            }
+        } else {
+            env::panic_str(errors::INSUFFICIENT_BALANCE);
+        }
+    }
+
+    /// Get account's available token balance (balance not locked in orders).
+    pub fn get_balance(&self, token: &TokenType) -> Balance {
+        let key = token.key();
+        self.balances.0.get(&key).cloned().unwrap_or_default()
+    }
+
+    /// Get all account available token balances (balances not locked in orders).
+    pub fn get_balances(&self) -> Vec<(String, Balance)> {
+        self.balances.0.clone().into_iter().collect()
+    }
+
+    /// Record an amount of `token` locked in a newly posted order. The amount
+    /// must already have been withdrawn from the free balance.
+    pub fn lock(&mut self, token: &TokenType, amount: Balance) {
+        if amount > 0 {
+            self.balance_deltas.record_locked(token, amount as i128);
+            *self.locked.0.entry(token.key()).or_default() += amount;
+        }
+    }
+
+    /// Release an amount of `token` locked in an order that was filled or
+    /// cancelled. Does not credit the free balance.
+    pub fn unlock(&mut self, token: &TokenType, amount: Balance) {
+        let key = token.key();
+        if let Some(x) = self.locked.0.get(&key).cloned() {
+            self.balance_deltas
+                .record_locked(token, -(x.min(amount) as i128));
+            if x <= amount {
+                self.locked.0.remove(&key);
+            } else {
+                self.locked.0.insert(key, x - amount);
+            }
+        }
+    }
+
+    /// Get account's balance of `token` locked in open orders.
+    pub fn get_locked_balance(&self, token: &TokenType) -> Balance {
+        self.locked.0.get(&token.key()).cloned().unwrap_or_default()
+    }
+
+    /// Get all of the account's balances locked in open orders.
+    pub fn get_locked_balances(&self) -> Vec<(String, Balance)> {
+        self.locked.0.clone().into_iter().collect()
+    }
+
+    /// Take the balance changes recorded since the account was loaded.
+    pub fn take_balance_deltas(&mut self) -> BalanceDeltas {
+        std::mem::take(&mut self.balance_deltas)
+    }
+
+    /// Replace locked balances, eg, after recomputing them for a migrated
+    /// account.
+    pub fn set_locked_balances(&mut self, locked: HashMap<String, Balance>) {
+        self.locked = TokenBalancesMap(locked);
+    }
+
+    /// Depends on DEX having a token
+    pub fn get_fee_tier(&self) -> fees::FeeTier {
+        0.into()
+    }
+
+    /// Save order metadata of a newly opened order on the account. Called when
+    /// an order is posted, used to get a list of an account's open orders.
+    pub fn save_new_order_info(
+        &mut self,
+        market_id: &MarketId,
+        order_id: OrderId,
+        original_size: LotBalance,
+        max_allowed_orders: usize,
+    ) {
+        let timestamp = env::block_timestamp();
+        match self.open_orders.0.get_mut(market_id) {
+            Some(orders_in_market) => {
+                #[cfg(not(feature = "no_order_limit"))]
+                if orders_in_market.len() >= max_allowed_orders {
+                    env::panic_str(errors::EXCEEDED_ORDER_LIMIT);
+                }
+                orders_in_market.insert(order_id, (original_size, timestamp));
+            }
+            None => {
+                let mut orders_in_market = HashMap::new();
+                orders_in_market.insert(order_id, (original_size, timestamp));
+                self.open_orders.0.insert(*market_id, orders_in_market);
+            }
+        };
+    }
+
+    /// Delete all of an account's order IDs for a market. Used whene cancelling
+    /// all orders in a market.
+    pub fn remove_all_order_infos(&mut self, market_id: &MarketId) -> Vec<OrderId> {
+        let n_removed = self.open_orders.0.get(market_id).map_or(0, |o| o.len());
+        self.release_orderbook_storage(n_removed as u64);
+        if let Some(existing) = self.open_orders.0.remove(market_id) {
+            existing.into_keys().collect()
+        } else {
+            vec![]
+        }
+    }
+
+    /// Find information about one of the account's open orders, if it exists.
+    pub fn get_order_info(
+        &self,
+        market_id: &MarketId,
+        order_id: &OrderId,
+    ) -> Option<(LotBalance, Timestamp)> {
+        self.open_orders.0.get(market_id)?.get(order_id).cloned()
+    }
+
+    pub fn remove_order_info(
+        &mut self,
+        market_id: &MarketId,
+        order_id: OrderId,
+    ) -> Option<OrderId> {
+        if self.get_order_info(market_id, &order_id).is_some() {
+            self.release_orderbook_storage(1);
+        }
+        let mut ret = None;
+        if let Some(orders) = self.open_orders.0.get_mut(market_id) {
+            if orders.remove(&order_id).is_some() {
+                if orders.is_empty() {
+                    self.open_orders.0.remove(market_id);
+                }
+                ret = Some(order_id);
+            }
+        }
+        ret
+    }
+
+    /// Charge the account for orderbook storage used by its newly posted
+    /// order. `storage_increase` is the net change in storage measured when
+    /// the market was saved, which is offset by storage released by maker
+    /// orders removed while matching.
+    pub fn charge_orderbook_storage(&mut self, storage_increase: i64) {
+        let released = std::mem::take(&mut self.orderbook_storage_released);
+        let charge = storage_increase + released as i64;
+        if charge > 0 {
+            self.orderbook_storage += charge as StorageUsage;
+        }
+    }
+
+    /// Record orderbook storage released by other accounts' orders while
+    /// matching this account's order.
+    pub fn offset_orderbook_storage(&mut self, released: StorageUsage) {
+        self.orderbook_storage_released += released;
+    }
+
+    /// Bytes of orderbook storage used by the account's open orders.
+    pub fn get_orderbook_storage(&self) -> StorageUsage {
+        self.orderbook_storage
+    }
+
+    /// Release the orderbook storage of `n_removed` orders that are about to
+    /// be removed. Orders don't record their own size, so each releases an
+    /// equal share.
+    fn release_orderbook_storage(&mut self, n_removed: u64) {
+        let n_orders = self.open_orders.n_orders();
+        if n_removed > 0 && n_orders > 0 {
+            self.orderbook_storage -= self.orderbook_storage * n_removed.min(n_orders) / n_orders;
+        }
+    }
+
+    pub fn open_orders_iter(
+        &self,
+        market_id: &MarketId,
+    ) -> impl Iterator<Item = (OrderId, (LotBalance, Timestamp))> {
+        self.open_orders.market_orders_iter(market_id)
+    }
+
+    /// IDs of markets where the account has open orders.
+    pub fn open_order_market_ids(&self) -> Vec<MarketId> {
+        self.open_orders.market_ids()
+    }
+
+    /// Return true if the account is empty, ie, has no open orders and no
+    /// exchange balances.
+    pub fn is_empty(&self) -> bool {
+        self.balances.is_empty() && self.open_orders.is_empty()
+    }
+}
+
+impl Account {
+    /// Bytes of storage the account pays for: its own record, its orders in
+    /// orderbooks, and room for the balances its orders can be filled into.
+    pub fn storage_usage(&self) -> StorageUsage {
+        self.borsh_size()
+            + self.orderbook_storage
+            + self.open_orders.0.len() as StorageUsage * size::MARKET_PAIR_OVERHEAD
+    }
+
+    /// Storage used by an empty account with an ID of maximum length.
+    pub fn max_empty_storage_usage() -> StorageUsage {
+        let mut account = Account::new(&env::current_account_id());
+        account.id = None;
+        account.storage_usage()
+    }
+
+    pub fn storage_balance_locked(&self) -> Balance {
+        Balance::from(self.storage_usage().saturating_sub(self.storage_grace))
+            * env::storage_byte_cost()
+    }
+
+    /// Cover the shortfall of an account converted from an older layout,
+    /// whose storage balance was paid at the sizes assumed back then.
+    pub fn grant_storage_grace(&mut self) {
+        self.storage_grace = self.storage_shortfall();
+    }
+
+    /// Shrink the grace to the current shortfall, so that storage the account
+    /// stops using isn't covered again.
+    pub fn shrink_storage_grace(&mut self) {
+        self.storage_grace = self.storage_grace.min(self.storage_shortfall());
+    }
+
+    fn storage_shortfall(&self) -> StorageUsage {
+        let paid = self.storage_balance / env::storage_byte_cost();
+        self.storage_usage()
+            .saturating_sub(paid.min(StorageUsage::MAX as Balance) as StorageUsage)
+    }
+
+    pub fn is_storage_covered(&self) -> bool {
+        self.storage_balance_locked() <= self.storage_balance
+    }
+
+    /// Storage balance that can be withdrawn: what isn't used, and wasn't
+    /// paid from the registration pool.
+    pub fn storage_balance_available(&self) -> Balance {
+        self.storage_balance.saturating_sub(std::cmp::max(
+            self.storage_balance_locked(),
+            self.sponsored_storage,
+        ))
+    }
+
+    /// Return true if the account's storage is covered, or can be covered
+    /// from its NEAR trading balance with `auto_storage`.
+    pub fn can_cover_storage(&self) -> bool {
+        self.is_storage_covered()
+            || (self.auto_storage
+                && self.storage_balance + self.get_balance(&TokenType::NativeNear)
+                    >= self.storage_balance_locked())
+    }
+
+    /// Move NEAR between the trading balance and `storage_balance`: release
+    /// auto storage that's no longer needed, then cover any shortfall from the
+    /// trading balance. Returns the net amount moved into storage, negative if
+    /// NEAR was released to the trading balance.
+    pub fn rebalance_auto_storage(&mut self) -> i128 {
+        // storage may have been withdrawn since it was moved in
+        self.auto_storage_balance = self.auto_storage_balance.min(self.storage_balance);
+        let before = self.auto_storage_balance;
+
+        let excess = self
+            .storage_balance
+            .saturating_sub(self.storage_balance_locked())
+            .min(self.auto_storage_balance);
+        if excess > 0 {
+            self.storage_balance -= excess;
+            self.auto_storage_balance -= excess;
+            self.deposit(&TokenType::NativeNear, excess);
+        }
+
+        // releasing can add a NEAR balance entry, which needs storage itself
+        let shortfall = self
+            .storage_balance_locked()
+            .saturating_sub(self.storage_balance)
+            .min(self.get_balance(&TokenType::NativeNear));
+        if shortfall > 0 {
+            self.withdraw(&TokenType::NativeNear, shortfall);
+            self.storage_balance += shortfall;
+            self.auto_storage_balance += shortfall;
+        }
+
+        self.auto_storage_balance as i128 - before as i128
+    }
+}
@@ -35,6 +35,23 @@ pub struct CancelAllOrdersAction {
     pub market_id: MarketId,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferAction {
+    pub receiver_id: AccountId,
+    pub token: String,
+    pub amount: U128,
+    pub memo: Option<String>,
+}
+
+/// Credit a token transfer to another registered account. Only valid as a
+/// token transfer message.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepositAction {
+    pub account_id: AccountId,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde", tag = "action", content = "params")]
 pub enum Action {
@@ -42,6 +59,11 @@ pub enum Action {
     CancelOrders(CancelOrdersAction),
     CancelAllOrders(CancelAllOrdersAction),
     Swap(Vec<SwapAction>),
+    Transfer(TransferAction),
+    Deposit(DepositAction),
+    /// Register the sender on a token transfer if needed. See
+    /// [Contract::prepay_storage].
+    Register,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,6 +74,19 @@ pub enum ActionResult {
 
     #[serde(rename = "cancelled")]
     Cancel(Vec<OrderId>),
+
+    #[serde(rename = "transferred")]
+    Transfer(U128),
+}
+
+/// Message of an `ft_transfer_call` or `mt_transfer_call` to the exchange:
+/// one action, or a list of actions to run after depositing. Apart from a
+/// single swap or deposit, only new orders and `Register` are allowed.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde", untagged)]
+pub enum TransferMessage {
+    Action(Action),
+    Actions(Vec<Action>),
 }
 
 #[near_bindgen]
@@ -62,26 +97,54 @@ impl Contract {
     pub fn execute(&mut self, actions: Vec<Action>) -> Vec<ActionResult> {
         assert_one_yocto();
 
+        let account_id = env::predecessor_account_id();
+        self.internal_execute(&account_id, actions)
+    }
+}
+
+impl Contract {
+    /// Executes actions on behalf of `account_id`. Swaps and deposits are
+    /// only valid as token transfer messages.
+    pub fn internal_execute(
+        &mut self,
+        account_id: &AccountId,
+        actions: Vec<Action>,
+    ) -> Vec<ActionResult> {
         let mut results = vec![];
-        for action in actions.iter() {
+        for action in actions {
             let result = match action {
                 Action::NewOrder(NewOrderAction { market_id, params }) => {
-                    let res = self.new_order(*market_id, params.clone());
+                    let res = self.internal_new_order(&market_id, account_id.clone(), params);
                     ActionResult::Order(res)
                 }
                 Action::CancelOrders(CancelOrdersAction {
                     market_id,
                     order_ids,
                 }) => {
+                    self.assert_can_cancel();
                     for order_id in order_ids.iter() {
-                        self.cancel_order(*market_id, *order_id);
+                        self.internal_cancel_order(market_id, account_id.clone(), *order_id);
                     }
-                    ActionResult::Cancel(order_ids.to_vec())
+                    ActionResult::Cancel(order_ids)
                 }
                 Action::CancelAllOrders(CancelAllOrdersAction { market_id }) => {
-                    let order_ids = self.cancel_all_orders(*market_id);
+                    self.assert_active();
+                    self.assert_can_cancel();
+                    let order_ids = self.internal_cancel_all_orders(&market_id, account_id.clone());
                     ActionResult::Cancel(order_ids)
                 }
+                Action::Transfer(TransferAction {
+                    receiver_id,
+                    token,
+                    amount,
+                    memo,
+                }) => {
+                    self.assert_active();
+                    let token = TokenType::from_key(&token);
+                    self.internal_transfer(account_id, &receiver_id, &token, amount.0, memo);
+                    ActionResult::Transfer(amount)
+                }
+                Action::Register => continue,
                 _ => {
                     env::panic_str(INVALID_ACTION);
                 }
@@ -91,4 +154,52 @@ impl Contract {
 
         results
     }
+
+    /// Executes the actions of a token transfer message for `account_id`
+    /// after `deposits` were credited to it. The token contract names the
+    /// sender, so only new orders and registration are allowed, and orders
+    /// can only spend the transferred tokens, up to the transferred amounts.
+    pub fn internal_execute_transfer_actions(
+        &mut self,
+        account_id: &AccountId,
+        deposits: &[(TokenType, Balance)],
+        actions: Vec<Action>,
+    ) {
+        for action in actions.iter() {
+            match action {
+                Action::NewOrder(NewOrderAction { market_id, params }) => {
+                    let spent = self.internal_get_market_summary(market_id).map(|market| {
+                        match params.side {
+                            Side::Buy => market.quote_token.token_type.key(),
+                            Side::Sell => market.base_token.token_type.key(),
+                        }
+                    });
+                    _assert!(
+                        spent.map_or(false, |key| deposits
+                            .iter()
+                            .any(|(token, _)| token.key() == key)),
+                        "Orders in a transfer message must spend the transferred token"
+                    );
+                }
+                Action::Register => {}
+                _ => env::panic_str(INVALID_ACTION),
+            }
+        }
+
+        let balances_before: Vec<Balance> = {
+            let account = self.internal_unwrap_account(account_id);
+            deposits
+                .iter()
+                .map(|(token, _)| account.get_balance(token))
+                .collect()
+        };
+        self.internal_execute(account_id, actions);
+        let account = self.internal_unwrap_account(account_id);
+        for ((token, amount), before) in deposits.iter().zip(balances_before) {
+            _assert!(
+                account.get_balance(token) + amount >= before,
+                "Orders in a transfer message can only spend the transferred amount"
+            );
+        }
+    }
 }
@@ -14,31 +14,212 @@ impl Contract {
         self.owner_id = account_id;
     }
 
+    /// Set a market's state. The contract owner can set any state. The market
+    /// creator can switch their market between active and cancel-only, so
+    /// users can always cancel their orders, but can't activate a market
+    /// that's still waiting on token metadata or override a state set by the
+    /// owner.
     pub fn set_market_state(&mut self, market_id: MarketId, new_state: MarketState) {
-        self.assert_is_owner();
         let mut market = self.internal_unwrap_market(&market_id);
-        market.set_state(new_state);
+        let is_owner = env::predecessor_account_id() == self.owner_id;
+        if !is_owner {
+            self.assert_is_market_creator(&market);
+            _assert!(
+                new_state == MarketState::Active || new_state == MarketState::CancelOnly,
+                "Market creator can only set a market to active or cancel-only"
+            );
+            _assert!(
+                market.state != MarketState::Uninitialized,
+                "Market creator cannot change the state of an uninitialized market"
+            );
+            _assert!(
+                !market.state_set_by_owner,
+                "Market state was set by the owner"
+            );
+        }
+        market.set_state(new_state, is_owner);
         self.internal_save_market(&market.unwrap_id().clone(), market);
     }
 
+    /// Callable by the contract owner or the market creator.
     pub fn set_market_bid_window(&mut self, market_id: MarketId, minimum_bid_bps: u32) {
-        self.assert_is_owner();
         let mut market = self.internal_unwrap_market(&market_id);
+        self.assert_is_owner_or_market_creator(&market);
         market.minimum_bid_bps = minimum_bid_bps;
         self.internal_save_market(&market.unwrap_id().clone(), market);
     }
 
+    /// Callable by the contract owner or the market creator.
     pub fn set_market_ask_window(&mut self, market_id: MarketId, maximum_ask_bps: u32) {
-        self.assert_is_owner();
         let mut market = self.internal_unwrap_market(&market_id);
+        self.assert_is_owner_or_market_creator(&market);
         market.maximum_ask_bps = maximum_ask_bps;
         self.internal_save_market(&market.unwrap_id().clone(), market);
     }
 
+    /// Set the price tick size of a market. Callable by the contract owner or
+    /// the market creator.
+    pub fn set_market_tick_size(&mut self, market_id: MarketId, tick_size: U128) {
+        let mut market = self.internal_unwrap_market(&market_id);
+        self.assert_is_owner_or_market_creator(&market);
+        market.assert_valid_tick_size(tick_size.0);
+        market.tick_size = tick_size.0;
+        self.internal_save_market(&market_id, market);
+    }
+
+    /// Mark a market as verified, ie, listing the tokens its symbol claims.
+    /// Only callable by the contract owner.
+    pub fn set_market_verified(&mut self, market_id: MarketId, verified: bool) {
+        self.assert_is_owner();
+        let mut market = self.internal_unwrap_market(&market_id);
+        market.verified = verified;
+        self.internal_save_market(&market_id, market);
+    }
+
+    /// Override a market's display symbols. Only callable by the contract
+    /// owner.
+    pub fn set_market_symbols(
+        &mut self,
+        market_id: MarketId,
+        base_symbol: Option<String>,
+        quote_symbol: Option<String>,
+    ) {
+        self.assert_is_owner();
+        let mut market = self.internal_unwrap_market(&market_id);
+        self.internal_set_market_symbol(&mut market, &PairSide::Base, base_symbol);
+        self.internal_set_market_symbol(&mut market, &PairSide::Quote, quote_symbol);
+        self.internal_save_market(&market_id, market);
+    }
+
+    /// Set the creator fee share for markets created from now on. Only
+    /// callable by the contract owner.
+    pub fn set_creator_fee_share(&mut self, creator_fee_share_bps: u32) {
+        self.assert_is_owner();
+        assert_valid_fee_share(creator_fee_share_bps);
+        self.creator_fee_share_bps = creator_fee_share_bps;
+    }
+
+    /// Set the creator fee share for an existing market. Only callable by the
+    /// contract owner.
+    pub fn set_market_creator_fee_share(
+        &mut self,
+        market_id: MarketId,
+        creator_fee_share_bps: u32,
+    ) {
+        self.assert_is_owner();
+        assert_valid_fee_share(creator_fee_share_bps);
+        let mut market = self.internal_unwrap_market(&market_id);
+        market.creator_fee_share_bps = creator_fee_share_bps;
+        self.internal_save_market(&market_id, market);
+    }
+
+    /// Set or clear a market's creator, eg, for markets created before
+    /// creators were recorded. Only callable by the contract owner.
+    pub fn set_market_creator(&mut self, market_id: MarketId, creator_id: Option<AccountId>) {
+        self.assert_is_owner();
+        let mut market = self.internal_unwrap_market(&market_id);
+        market.creator_id = creator_id;
+        self.internal_save_market(&market_id, market);
+    }
+
+    /// Move the creator's share of fees to their exchange balance. Only
+    /// callable by the market creator. Returns the amount claimed.
+    pub fn claim_creator_fees(&mut self, market_id: MarketId) -> U128 {
+        self.assert_active();
+        let mut market = self.internal_unwrap_market(&market_id);
+        self.assert_is_market_creator(&market);
+
+        let account_id = env::predecessor_account_id();
+        let amount = market.creator_fees_accrued;
+        if amount > 0 {
+            market.creator_fees_accrued = 0;
+            self.internal_decr_fees(&market.quote_token.token_type, amount);
+            self.internal_deposit(&account_id, &market.quote_token.token_type, amount);
+            self.internal_save_market(&market_id, market);
+        }
+
+        amount.into()
+    }
+
+    /// Add a token to the registry, or update the decimals, symbol, and deposit
+    /// cap of a registered token. New tokens are allowed. Only callable by the
+    /// contract owner.
+    ///
+    /// If decimals are provided, markets listing the token are created active
+    /// without waiting for a token metadata callback.
+    pub fn register_token(
+        &mut self,
+        token: String,
+        decimals: Option<u8>,
+        symbol: Option<String>,
+        deposit_cap: Option<U128>,
+    ) {
+        self.assert_is_owner();
+        let token = TokenType::from_key(&token);
+        let existing = self.tokens.get(&token.key());
+        self.internal_register_token(
+            &token,
+            TokenInfo {
+                status: existing
+                    .as_ref()
+                    .map(|info| info.status)
+                    .unwrap_or(TokenStatus::Allowed),
+                decimals,
+                symbol,
+                deposit_cap: deposit_cap.map(|c| c.0),
+                min_sponsored_deposit: existing.and_then(|info| info.min_sponsored_deposit),
+            },
+        );
+    }
+
+    /// Allow or block a registered token. Blocked tokens can't be listed or
+    /// deposited. Only callable by the contract owner.
+    pub fn set_token_status(&mut self, token: String, status: TokenStatus) {
+        self.assert_is_owner();
+        let token = TokenType::from_key(&token);
+        let mut info = _expect!(
+            self.internal_get_token_info(&token),
+            "Token is not registered"
+        );
+        info.status = status;
+        self.internal_register_token(&token, info);
+    }
+
+    /// Set or clear the maximum single deposit for a registered token. Only
+    /// callable by the contract owner.
+    pub fn set_token_deposit_cap(&mut self, token: String, deposit_cap: Option<U128>) {
+        self.assert_is_owner();
+        let token = TokenType::from_key(&token);
+        let mut info = _expect!(
+            self.internal_get_token_info(&token),
+            "Token is not registered"
+        );
+        info.deposit_cap = deposit_cap.map(|c| c.0);
+        self.internal_register_token(&token, info);
+    }
+
+    /// Set or clear the smallest deposit of a registered token that can
+    /// register its sender from the registration pool. Only callable by the
+    /// contract owner.
+    pub fn set_token_min_sponsored_deposit(&mut self, token: String, amount: Option<U128>) {
+        self.assert_is_owner();
+        let token = TokenType::from_key(&token);
+        let mut info = _expect!(
+            self.internal_get_token_info(&token),
+            "Token is not registered"
+        );
+        info.min_sponsored_deposit = amount.map(|a| a.0);
+        self.internal_register_token(&token, info);
+    }
+
     /// Delete a market. Market must be uninitialized or paused with no resting
-    /// orders. Only callable by the contract owner.
+    /// orders. Only callable by the contract owner. The market's storage
+    /// deposit and any unclaimed creator fees go back to the creator, and its
+    /// protocol fees are credited to the owner.
     pub fn admin_delete_market(&mut self, market_id: MarketId) {
         self.assert_is_owner();
+        // deleting reorders `market_iter_map` under the migration cursor
+        self.assert_markets_migrated();
 
         let market = self.internal_unwrap_market(&market_id);
         let can_delete = match market.state {
@@ -61,6 +242,26 @@ impl Contract {
                 "bug: deleted market id and passed market id are different"
             );
             self.markets.remove(&market_id);
+            self.market_stats.remove(&market_id);
+            self.top_of_book.remove(&market_id);
+            self.market_headers.remove(&market_id);
+            self.internal_remove_candles(&market_id);
+            self.oracles.remove(&market_id);
+            self.internal_unindex_market(&market_id, &market);
+            // protocol fees of a deleted market are paid out to the owner
+            if market.fees_accrued > 0 {
+                let owner_id = self.owner_id.clone();
+                self.internal_decr_fees(&market.quote_token.token_type, market.fees_accrued);
+                self.internal_credit_or_escrow(
+                    &owner_id,
+                    &market.quote_token.token_type,
+                    market.fees_accrued,
+                );
+            }
+
+            if let Some(creator_id) = &market.creator_id {
+                self.internal_refund_market_creator(creator_id, &market);
+            }
         }
     }
 
@@ -99,3 +300,44 @@ impl Contract {
         self.internal_clear_orderbook_orders(&market_id, limit)
     }
 }
+
+impl Contract {
+    pub fn assert_is_market_creator(&self, market: &Market) {
+        _assert!(
+            market.is_creator(&env::predecessor_account_id()),
+            "Method can only be called by the market creator"
+        );
+    }
+
+    pub fn assert_is_owner_or_market_creator(&self, market: &Market) {
+        let caller = env::predecessor_account_id();
+        _assert!(
+            caller == self.owner_id || market.is_creator(&caller),
+            "Method can only be called by contract owner ID or market creator"
+        );
+    }
+
+    /// Refund the storage deposit of a deleted market and credit unclaimed
+    /// creator fees to the creator's exchange balance. Fees are held as
+    /// unclaimed if the creator no longer has an account to hold them.
+    fn internal_refund_market_creator(&mut self, creator_id: &AccountId, market: &Market) {
+        if market.creator_fees_accrued > 0 {
+            self.internal_decr_fees(&market.quote_token.token_type, market.creator_fees_accrued);
+            self.internal_credit_or_escrow(
+                creator_id,
+                &market.quote_token.token_type,
+                market.creator_fees_accrued,
+            );
+        }
+        if market.storage_deposit > 0 {
+            Promise::new(creator_id.clone()).transfer(market.storage_deposit);
+        }
+    }
+}
+
+fn assert_valid_fee_share(bps: u32) {
+    _assert!(
+        bps as u128 <= FEE_TO_BPS_DIVISOR,
+        "Fee share cannot be more than 100%"
+    );
+}
@@ -1,15 +1,20 @@
 /// Implements structs and methods for working with exchange balances.
 use near_contract_standards::fungible_token::core_impl::ext_fungible_token;
 use near_sdk::json_types::U128;
-use near_sdk::{ext_contract, Gas, PromiseResult};
+use near_sdk::{ext_contract, serde_json, Gas, PromiseResult};
 use std::string::ToString;
 
 use crate::*;
 
-/// 5 TGas
-pub const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(5_000_000_000_000);
+/// 10 TGas, enough for a failed transfer held as unclaimed, which writes the
+/// escrow entry and the registration pool and emits an event
+pub const GAS_FOR_RESOLVE_TRANSFER: Gas = Gas(10_000_000_000_000);
 /// 10 TGas
 pub const GAS_FOR_FT_TRANSFER: Gas = Gas(10_000_000_000_000);
+/// 10 TGas
+pub const GAS_FOR_RESOLVE_WITHDRAW_MANY: Gas = Gas(10_000_000_000_000);
+/// 50 TGas, most of which is left for the receiver's `ft_on_transfer`
+pub const GAS_FOR_FT_TRANSFER_CALL: Gas = Gas(50_000_000_000_000);
 
 #[ext_contract(ext_self)]
 pub trait TonicExchange {
@@ -18,7 +23,15 @@ pub trait TonicExchange {
         token: TokenType,
         receiver_id: AccountId,
         amount: U128,
-    );
+        is_call: Option<bool>,
+    ) -> U128;
+
+    fn exchange_callback_post_withdraw_many(
+        &mut self,
+        account_id: AccountId,
+        count: u32,
+        unregister: bool,
+    ) -> bool;
 }
 
 #[near_bindgen]
@@ -27,13 +40,37 @@ impl Contract {
     pub fn deposit_near(&mut self) {
         self.assert_active();
 
-        let amount = env::attached_deposit();
+        let mut amount = env::attached_deposit();
+        self.assert_can_deposit(&TokenType::NativeNear, amount);
         let sender_id = env::predecessor_account_id();
+        if self.internal_get_account(&sender_id).is_none() {
+            // register, paying for storage from the prepayment, then the deposit
+            let cost = self.internal_registration_cost(&sender_id, &TokenType::NativeNear);
+            let prepaid = self.internal_take_storage_prepayment(&sender_id);
+            let used = cost.saturating_sub(prepaid);
+            _assert!(amount > used, errors::INSUFFICIENT_STORAGE_BALANCE);
+            let mut account = Account::new(&sender_id);
+            account.storage_balance = prepaid + used;
+            self.internal_save_account(&sender_id, account);
+            amount -= used;
+        }
         let mut account = self.internal_unwrap_account(&sender_id);
         account.deposit(&TokenType::NativeNear, amount);
+        self.internal_record_deposit(&TokenType::NativeNear, amount);
         self.internal_save_account(&sender_id, account);
     }
 
+    /// Deposit the attached NEAR to another registered account.
+    #[payable]
+    pub fn deposit_near_for(&mut self, account_id: AccountId) {
+        self.assert_active();
+
+        let amount = env::attached_deposit();
+        self.assert_can_deposit(&TokenType::NativeNear, amount);
+        self.internal_deposit(&account_id, &TokenType::NativeNear, amount);
+        self.internal_record_deposit(&TokenType::NativeNear, amount);
+    }
+
     #[payable]
     pub fn withdraw_near(&mut self, amount: U128) {
         self.assert_active();
@@ -64,13 +101,164 @@ impl Contract {
         self.internal_withdraw(&account_id, &token, amount.into());
     }
 
+    /// Withdraw to any account. If `msg` is set, fungible tokens are sent with
+    /// `ft_transfer_call`, and any amount the receiver refunds is credited
+    /// back to the caller's balance. `receiver_id` defaults to the caller.
+    ///
+    /// If `storage_payment` is set, fungible token withdrawals first check
+    /// that the receiver is registered with the token, and register them if
+    /// not, paid for as chosen.
+    #[payable]
+    pub fn withdraw(
+        &mut self,
+        token: String,
+        amount: U128,
+        receiver_id: Option<AccountId>,
+        memo: Option<String>,
+        msg: Option<String>,
+        storage_payment: Option<StoragePayment>,
+    ) -> Promise {
+        self.assert_active();
+        assert_one_yocto();
+
+        let account_id = env::predecessor_account_id();
+        let receiver_id = receiver_id.unwrap_or_else(|| account_id.clone());
+        let token = TokenType::from_key(&token);
+        match (&token, storage_payment) {
+            (
+                TokenType::FungibleToken {
+                    account_id: token_id,
+                },
+                Some(payment),
+            ) => self.internal_withdraw_ft_checked(
+                &account_id,
+                &receiver_id,
+                token_id,
+                amount.into(),
+                memo,
+                msg,
+                payment,
+            ),
+            _ => self.internal_withdraw_to(
+                &account_id,
+                &receiver_id,
+                &token,
+                amount.into(),
+                memo,
+                msg,
+            ),
+        }
+    }
+
+    /// Withdraw several tokens to the caller. `None` withdraws the whole free
+    /// balance of a token. If `unregister` is true, the account is unregistered
+    /// and its storage balance refunded once every transfer has succeeded.
+    #[payable]
+    pub fn withdraw_many(
+        &mut self,
+        withdrawals: Vec<(TokenType, Option<U128>)>,
+        unregister: Option<bool>,
+    ) -> Promise {
+        self.assert_active();
+        assert_one_yocto();
+
+        let account_id = env::predecessor_account_id();
+        let mut account = self.internal_unwrap_account(&account_id);
+        let mut sends: Vec<(TokenType, Balance)> = vec![];
+        for (token, amount) in withdrawals {
+            let amount = match amount {
+                Some(amount) => {
+                    _assert!(amount.0 > 0, "Withdraw amount must be positive");
+                    amount.0
+                }
+                None => account.get_balance(&token),
+            };
+            if amount > 0 {
+                account.withdraw(&token, amount);
+                sends.push((token, amount));
+            }
+        }
+        _assert!(!sends.is_empty(), "Nothing to withdraw");
+        self.internal_save_account(&account_id, account);
+        self.internal_send_many(&account_id, sends, unregister.unwrap_or(false))
+    }
+
+    /// Runs after the per-token callbacks of [Contract::withdraw_many].
+    /// Unregisters the account if requested and nothing was credited back.
+    /// Returns true if the account was unregistered.
+    #[private]
+    pub fn exchange_callback_post_withdraw_many(
+        &mut self,
+        account_id: AccountId,
+        count: u32,
+        unregister: bool,
+    ) -> bool {
+        // NEAR transfers return nothing, token sends return the amount refunded
+        let all_sent = (0..count as u64).all(|i| match env::promise_result(i) {
+            PromiseResult::Successful(value) if value.is_empty() => true,
+            PromiseResult::Successful(value) => {
+                serde_json::from_slice::<U128>(&value).map_or(false, |refund| refund.0 == 0)
+            }
+            _ => false,
+        });
+        unregister && all_sent && self.internal_try_unregister_account(&account_id)
+    }
+
+    /// Withdraw funds held after a failed withdrawal or payout to an account
+    /// that no longer exists. Doesn't require a registered account.
+    #[payable]
+    pub fn claim_unclaimed(&mut self, token: String) -> Promise {
+        self.assert_active();
+        assert_one_yocto();
+
+        let account_id = env::predecessor_account_id();
+        let token = TokenType::from_key(&token);
+        let amount = self.internal_take_unclaimed(&account_id, &token);
+        _assert!(amount > 0, "No unclaimed balance");
+        emit_dex_event(DexEventType::ClaimUnclaimed(UnclaimedEvent {
+            account_id: account_id.clone(),
+            token: token.key(),
+            amount: U128(amount),
+        }));
+        self.internal_send_to(&account_id, &account_id, &token, amount, None, None)
+    }
+
+    /// Move free balance to another registered account. The receiver must
+    /// have enough storage balance to hold the token.
+    #[payable]
+    pub fn transfer(
+        &mut self,
+        receiver_id: AccountId,
+        token: String,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        self.assert_active();
+        assert_one_yocto();
+
+        let sender_id = env::predecessor_account_id();
+        let token = TokenType::from_key(&token);
+        self.internal_transfer(&sender_id, &receiver_id, &token, amount.into(), memo);
+    }
+
+    /// Credit back the part of a withdrawal that wasn't received: all of it if
+    /// the transfer failed, or the amount refunded by the receiver of an
+    /// `ft_transfer_call`. Returns the amount credited.
+    ///
+    /// `receiver_id` is the account credited, which isn't the receiver of the
+    /// transfer when sending to another account. The argument names and
+    /// types stay compatible with callbacks scheduled by older code, which
+    /// don't pass `is_call`.
     #[private]
     pub fn exchange_callback_post_withdraw(
         &mut self,
         token: &TokenType,
         receiver_id: AccountId,
         amount: U128,
-    ) {
+        is_call: Option<bool>,
+    ) -> U128 {
+        let account_id = receiver_id;
+        let is_call = is_call.unwrap_or(false);
         debug_log!(
             "exchange cb post withdraw token {}, amount {}",
             token.to_string(),
@@ -82,61 +270,249 @@ impl Contract {
             "{}",
             "expected one promise result post-withdraw"
         );
-        match env::promise_result(0) {
+        let refund = match env::promise_result(0) {
             PromiseResult::NotReady => unreachable!(),
-            PromiseResult::Successful(_) => {}
-            PromiseResult::Failed => {
-                // This reverts the changes from withdraw function.
-                // If account no longer exists, deposit to the token owner's account.
-                if self.internal_get_account(&receiver_id).is_some() {
-                    self.internal_deposit(&receiver_id, token, amount.0);
-                } else {
-                    env::log_str(&format!(
-                        "Account {} is not registered. Depositing to contract owner.",
-                        receiver_id
-                    ));
-                    self.internal_deposit(&self.owner_id.clone(), token, amount.0);
-                }
+            PromiseResult::Successful(value) if is_call => {
+                // ft_resolve_transfer returns the amount the receiver kept
+                let used = serde_json::from_slice::<U128>(&value)
+                    .map(|used| used.0)
+                    .unwrap_or(amount.0);
+                amount.0 - used.min(amount.0)
             }
+            PromiseResult::Successful(_) => 0,
+            PromiseResult::Failed => amount.0,
         };
+        if refund == 0 {
+            return U128(0);
+        }
+
+        self.internal_revert_withdrawal(token, refund);
+        // This reverts the changes from withdraw function.
+        self.internal_credit_or_escrow(&account_id, token, refund);
+        U128(refund)
     }
 }
 
+/// Funds held for an account that no longer exists.
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct UnclaimedBalances {
+    /// Amounts by token key.
+    pub balances: HashMap<String, Balance>,
+
+    /// Storage cost of the entry paid from the registration pool, returned to
+    /// the pool when the entry is removed.
+    pub sponsored_storage: Balance,
+}
+
 impl Contract {
+    pub fn internal_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        token: &TokenType,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        _assert!(amount > 0, "Transfer amount must be positive");
+        _assert!(
+            sender_id != receiver_id,
+            "Cannot transfer to the same account"
+        );
+
+        let mut sender = self.internal_unwrap_account(sender_id);
+        sender.withdraw(token, amount);
+        self.internal_save_account(sender_id, sender);
+
+        let mut receiver = self.internal_unwrap_account(receiver_id);
+        receiver.deposit(token, amount);
+        if self
+            .internal_try_save_account(receiver_id, receiver)
+            .is_err()
+        {
+            env::panic_str("Receiver has insufficient storage balance");
+        }
+
+        emit_dex_event(DexEventType::Transfer(TransferEvent {
+            sender_id: sender_id.clone(),
+            receiver_id: receiver_id.clone(),
+            token: token.key(),
+            amount: U128(amount),
+            memo,
+        }));
+    }
+
+    pub fn internal_escrow_unclaimed(
+        &mut self,
+        account_id: &AccountId,
+        token: &TokenType,
+        amount: Balance,
+    ) {
+        let mut unclaimed = self.unclaimed.get(account_id).unwrap_or_default();
+        *unclaimed.balances.entry(token.key()).or_default() += amount;
+        let storage_increase = measure_storage_increase!({
+            self.unclaimed.insert(account_id, &unclaimed);
+        });
+        // there's no account to charge for the entry, so the pool pays for
+        // as much of it as it can
+        let cost = Balance::from(storage_increase) * env::storage_byte_cost();
+        let sponsored = std::cmp::min(cost, self.registration_pool);
+        if sponsored > 0 {
+            self.registration_pool -= sponsored;
+            unclaimed.sponsored_storage += sponsored;
+            self.unclaimed.insert(account_id, &unclaimed);
+        }
+        self.internal_record_unclaimed(token, amount as i128);
+        emit_dex_event(DexEventType::EscrowUnclaimed(UnclaimedEvent {
+            account_id: account_id.clone(),
+            token: token.key(),
+            amount: U128(amount),
+        }));
+    }
+
+    /// Credit funds to an account's free balance. The account pays for the
+    /// storage even if it's short of storage balance. If the account no longer
+    /// exists, hold the funds until it claims them. Never panics, so it's safe
+    /// to use in callbacks.
+    pub fn internal_credit_or_escrow(
+        &mut self,
+        account_id: &AccountId,
+        token: &TokenType,
+        amount: Balance,
+    ) {
+        match self.internal_get_account(account_id) {
+            Some(mut account) => {
+                account.deposit(token, amount);
+                self.internal_save_maker_account(account_id, account);
+            }
+            None => self.internal_escrow_unclaimed(account_id, token, amount),
+        }
+    }
+
+    /// Remove and return an account's unclaimed balance of a token.
+    pub fn internal_take_unclaimed(
+        &mut self,
+        account_id: &AccountId,
+        token: &TokenType,
+    ) -> Balance {
+        let mut unclaimed = match self.unclaimed.get(account_id) {
+            Some(u) => u,
+            None => return 0,
+        };
+        let amount = unclaimed.balances.remove(&token.key()).unwrap_or_default();
+        if unclaimed.balances.is_empty() {
+            self.unclaimed.remove(account_id);
+            self.registration_pool += unclaimed.sponsored_storage;
+        } else {
+            self.unclaimed.insert(account_id, &unclaimed);
+        }
+        self.internal_record_unclaimed(token, -(amount as i128));
+        amount
+    }
+
     pub fn internal_withdraw(
         &mut self,
         account_id: &AccountId,
         token: &TokenType,
         amount: Balance,
+    ) -> Promise {
+        self.internal_withdraw_to(account_id, account_id, token, amount, None, None)
+    }
+
+    /// Debit `account_id` and send to `receiver_id`.
+    pub fn internal_withdraw_to(
+        &mut self,
+        account_id: &AccountId,
+        receiver_id: &AccountId,
+        token: &TokenType,
+        amount: Balance,
+        memo: Option<String>,
+        msg: Option<String>,
     ) -> Promise {
         _assert!(amount > 0, "Withdraw amount must be positive");
 
         let mut account = self.internal_unwrap_account(account_id);
         account.withdraw(token, amount);
         self.internal_save_account(account_id, account);
-        self.internal_send(account_id, token, amount)
+        self.internal_send_to(account_id, receiver_id, token, amount, memo, msg)
+    }
+
+    /// Send already debited balances of `account_id` to it, then run
+    /// [Contract::exchange_callback_post_withdraw_many], which unregisters the
+    /// account if `unregister` is set and every transfer succeeded.
+    pub fn internal_send_many(
+        &mut self,
+        account_id: &AccountId,
+        sends: Vec<(TokenType, Balance)>,
+        unregister: bool,
+    ) -> Promise {
+        let count = sends.len() as u32;
+        let promise = sends
+            .into_iter()
+            .map(|(token, amount)| self.internal_send(account_id, &token, amount))
+            .reduce(|acc, p| acc.and(p))
+            .unwrap();
+        promise.then(ext_self::exchange_callback_post_withdraw_many(
+            account_id.clone(),
+            count,
+            unregister,
+            env::current_account_id(),
+            0,
+            GAS_FOR_RESOLVE_WITHDRAW_MANY,
+        ))
     }
 
     pub fn internal_send(
-        &self,
+        &mut self,
         receiver_id: &AccountId,
         token: &TokenType,
         amount: Balance,
     ) -> Promise {
+        self.internal_send_to(receiver_id, receiver_id, token, amount, None, None)
+    }
+
+    /// Send tokens to `receiver_id`. Amounts that aren't received are
+    /// credited to `account_id`.
+    pub fn internal_send_to(
+        &mut self,
+        account_id: &AccountId,
+        receiver_id: &AccountId,
+        token: &TokenType,
+        amount: Balance,
+        memo: Option<String>,
+        msg: Option<String>,
+    ) -> Promise {
+        self.internal_record_withdrawal(token, amount);
+        let is_call = msg.is_some();
         match token {
-            TokenType::NativeNear => Promise::new(receiver_id.clone()).transfer(amount),
-            TokenType::FungibleToken { account_id } => {
-                self.internal_send_ft(receiver_id, account_id, amount)
+            TokenType::NativeNear => {
+                _assert!(
+                    !is_call,
+                    "Transfer calls are only supported for fungible tokens"
+                );
+                Promise::new(receiver_id.clone()).transfer(amount)
             }
+            TokenType::FungibleToken {
+                account_id: token_id,
+            } => match msg {
+                Some(msg) => self.internal_send_ft_call(receiver_id, token_id, amount, memo, msg),
+                None => self.internal_send_ft(receiver_id, token_id, amount, memo),
+            },
             TokenType::MultiFungibleToken {
-                account_id,
+                account_id: token_id,
                 subtoken_id,
-            } => self.internal_send_mft(receiver_id, account_id, subtoken_id, amount),
+            } => {
+                _assert!(
+                    !is_call,
+                    "Transfer calls are only supported for fungible tokens"
+                );
+                self.internal_send_mft(receiver_id, token_id, subtoken_id, amount, memo)
+            }
         }
         .then(ext_self::exchange_callback_post_withdraw(
             token.clone(),
-            receiver_id.clone(),
+            account_id.clone(),
             U128(amount),
+            Some(is_call),
             env::current_account_id(),
             0,
             GAS_FOR_RESOLVE_TRANSFER,
@@ -148,30 +524,51 @@ impl Contract {
         receiver_id: &AccountId,
         token_id: &AccountId,
         amount: Balance,
+        memo: Option<String>,
     ) -> Promise {
         ext_fungible_token::ft_transfer(
             receiver_id.clone(),
             U128(amount),
-            None,
+            memo,
             token_id.clone(),
             1,
             GAS_FOR_FT_TRANSFER,
         )
     }
 
+    pub fn internal_send_ft_call(
+        &self,
+        receiver_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+        msg: String,
+    ) -> Promise {
+        ext_fungible_token::ft_transfer_call(
+            receiver_id.clone(),
+            U128(amount),
+            memo,
+            msg,
+            token_id.clone(),
+            1,
+            GAS_FOR_FT_TRANSFER_CALL,
+        )
+    }
+
     pub fn internal_send_mft(
         &self,
         receiver_id: &AccountId,
         token_account_id: &AccountId,
         token_id: &str,
         amount: Balance,
+        memo: Option<String>,
     ) -> Promise {
         ext_multi_token::mt_transfer(
             receiver_id.clone(),
             token_id.into(),
             U128(amount),
             None,
-            None,
+            memo,
             token_account_id.clone(),
             1,
             GAS_FOR_FT_TRANSFER,
@@ -0,0 +1,126 @@
+/// Implements events for operations that don't have a tonic-sdk event type.
+/// Events are logged in the NEP-297 format, and like tonic-sdk events, are not
+/// logged when the `no_emit` feature is enabled.
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json;
+
+use crate::*;
+
+pub const DEX_EVENT_STANDARD: &str = "tonic";
+pub const DEX_EVENT_VERSION: &str = "1.0.0";
+
+/// Event name and data, serialized as the `event` and `data` fields of the
+/// NEP-297 log.
+#[derive(Serialize, Clone, Debug)]
+#[serde(
+    crate = "near_sdk::serde",
+    tag = "event",
+    content = "data",
+    rename_all = "snake_case"
+)]
+pub enum DexEventType {
+    AutoStorage(AutoStorageEvent),
+    Transfer(TransferEvent),
+    EscrowUnclaimed(UnclaimedEvent),
+    ClaimUnclaimed(UnclaimedEvent),
+    WithdrawReceiverStorage(WithdrawReceiverStorageEvent),
+    ReservesMismatch(ReservesMismatchEvent),
+    LiabilitiesMismatch(LiabilitiesMismatchEvent),
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum AutoStorageDirection {
+    ToStorage,
+    ToBalance,
+}
+
+/// NEAR moved between an account's trading balance and storage balance by
+/// `auto_storage`.
+#[derive(Serialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AutoStorageEvent {
+    pub account_id: AccountId,
+    pub amount: U128,
+    pub direction: AutoStorageDirection,
+}
+
+/// Free balance moved between two registered accounts.
+#[derive(Serialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferEvent {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub token: String,
+    pub amount: U128,
+    pub memo: Option<String>,
+}
+
+/// Funds held for, or claimed by, an account without an exchange balance to
+/// credit them to.
+#[derive(Serialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UnclaimedEvent {
+    pub account_id: AccountId,
+    pub token: String,
+    pub amount: U128,
+}
+
+/// Path taken by a withdrawal that checked the receiver's registration with
+/// the token. See [Contract::on_receiver_storage_checked] and
+/// [Contract::on_receiver_storage_deposit].
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum ReceiverStoragePath {
+    Registered,
+    Unchecked,
+    StorageDeposit,
+    StorageDepositFailed,
+    Refunded,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct WithdrawReceiverStorageEvent {
+    pub account_id: AccountId,
+    pub receiver_id: AccountId,
+    pub token: String,
+    pub path: ReceiverStoragePath,
+}
+
+/// The contract holds less of a token than it owes.
+#[derive(Serialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ReservesMismatchEvent {
+    pub token: AccountId,
+    pub balance: U128,
+    pub liabilities: U128,
+}
+
+/// A decrease to a liability total was larger than the total, which was set
+/// to zero. See [Contract::set_liabilities_baseline].
+#[derive(Serialize, Clone, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LiabilitiesMismatchEvent {
+    pub token: String,
+    pub field: LiabilityField,
+    pub total: U128,
+    pub decrease: U128,
+}
+
+pub fn emit_dex_event(event: DexEventType) {
+    #[cfg(not(feature = "no_emit"))]
+    {
+        let value = serde_json::to_value(&event).unwrap();
+        let log = serde_json::json!({
+            "standard": DEX_EVENT_STANDARD,
+            "version": DEX_EVENT_VERSION,
+            "event": value["event"],
+            "data": value["data"],
+        });
+        env::log_str(&format!("EVENT_JSON:{}", log));
+    }
+    #[cfg(feature = "no_emit")]
+    let _ = event;
+}
@@ -59,6 +59,12 @@ impl Contract {
     ) {
         let mut market = self.internal_unwrap_market(&market_id);
         if let Some(ft_metadata_value) = ft_metadata {
+            self.internal_save_token_metadata(
+                &mut market,
+                &pair_side,
+                ft_metadata_value.decimals,
+                Some(ft_metadata_value.symbol),
+            );
             market.set_decimals(pair_side, ft_metadata_value.decimals);
             if market.validate_lots_and_decimals() {
                 market.state = MarketState::Active;
@@ -90,6 +96,12 @@ impl Contract {
                 .and_then(|metadata| metadata.decimals.clone())
                 .map(|decimals_raw| decimals_raw.parse::<u8>())
             {
+                self.internal_save_token_metadata(
+                    &mut market,
+                    &pair_side,
+                    decimals,
+                    mt_metadata_value[0].symbol.clone(),
+                );
                 market.set_decimals(pair_side, decimals);
                 if market.base_token.decimals != INVALID_DECIMALS
                     && market.quote_token.decimals != INVALID_DECIMALS
@@ -111,6 +123,28 @@ impl Contract {
     }
 }
 
+impl Contract {
+    /// Cache token metadata in the token registry and set the market's display
+    /// symbol for the token. Symbols set in the registry take precedence over
+    /// the symbol from metadata.
+    fn internal_save_token_metadata(
+        &mut self,
+        market: &mut Market,
+        pair_side: &PairSide,
+        decimals: u8,
+        symbol: Option<String>,
+    ) {
+        let token = market.token(pair_side).clone();
+        let symbol = symbol.filter(|s| !s.is_empty());
+        self.internal_cache_token_metadata(&token, decimals, symbol.clone());
+        let symbol = self
+            .internal_get_token_info(&token)
+            .and_then(|info| info.symbol)
+            .or(symbol);
+        self.internal_set_market_symbol(market, pair_side, symbol);
+    }
+}
+
 /// Metadata for the collection of tokens on the contract
 /// TODO: remove this and import directly from near_contract_standards after MT is merged.
 #[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
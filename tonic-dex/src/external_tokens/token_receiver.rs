@@ -21,15 +21,35 @@ impl FungibleTokenReceiver for Contract {
         let token = TokenType::FungibleToken {
             account_id: token_id,
         };
+        self.assert_can_deposit(&token, amount);
+        self.internal_record_deposit(&token, amount);
         if msg.is_empty() {
             self.internal_deposit(&sender_id, &token, amount);
             return PromiseOrValue::Value(U128(0));
         }
-        let message = serde_json::from_str::<Action>(&msg).expect("Invalid message");
-        match message {
-            Action::Swap(swaps) => self.execute_swaps(swaps, token, amount, sender_id),
-            _ => env::panic_str(INVALID_ACTION),
+        let message = serde_json::from_str::<TransferMessage>(&msg).expect("Invalid message");
+        let actions = match message {
+            TransferMessage::Action(Action::Swap(swaps)) => {
+                // the token contract refunds what the swaps didn't use
+                let refund = self.execute_swaps(swaps, token, amount, sender_id);
+                return PromiseOrValue::Value(U128(refund));
+            }
+            TransferMessage::Action(Action::Deposit(DepositAction { account_id })) => {
+                self.internal_deposit(&account_id, &token, amount);
+                return PromiseOrValue::Value(U128(0));
+            }
+            TransferMessage::Action(action) => vec![action],
+            TransferMessage::Actions(actions) => actions,
+        };
+        if actions.iter().any(|a| matches!(a, Action::Register))
+            && self.internal_get_account(&sender_id).is_none()
+        {
+            self.internal_auto_register(&sender_id, &token, amount);
         }
+        // whatever the actions don't use stays in the sender's balance
+        self.internal_deposit(&sender_id, &token, amount);
+        self.internal_execute_transfer_actions(&sender_id, &[(token, amount)], actions);
+        PromiseOrValue::Value(U128(0))
     }
 }
 
@@ -65,14 +85,27 @@ pub trait MultiTokenReceiver {
 impl MultiTokenReceiver for Contract {
     fn mt_on_transfer(
         &mut self,
-        _sender_id: AccountId,
+        sender_id: AccountId,
         previous_owner_ids: Vec<AccountId>,
         token_ids: Vec<TokenId>,
         amounts: Vec<U128>,
-        _msg: String,
+        msg: String,
     ) -> PromiseOrValue<Vec<U128>> {
         self.assert_active();
 
+        let (receiver_id, actions) = if msg.is_empty() {
+            (None, vec![])
+        } else {
+            match serde_json::from_str::<TransferMessage>(&msg).expect("Invalid message") {
+                TransferMessage::Action(Action::Deposit(DepositAction { account_id })) => {
+                    (Some(account_id), vec![])
+                }
+                TransferMessage::Action(Action::Swap(_)) => env::panic_str(INVALID_ACTION),
+                TransferMessage::Action(action) => (None, vec![action]),
+                TransferMessage::Actions(actions) => (None, actions),
+            }
+        };
+
         let account_id = env::predecessor_account_id();
         _assert_eq!(
             token_ids.len(),
@@ -85,21 +118,31 @@ impl MultiTokenReceiver for Contract {
             "Token list length does not match previous_owner_ids"
         );
         let mut results: Vec<U128> = vec![];
+        let mut deposits: Vec<(TokenType, Balance)> = vec![];
         let it = token_ids
             .iter()
             .zip(amounts.iter())
             .zip(previous_owner_ids.iter());
         for (_i, ((subtoken_id, &amount), prev_owner_id)) in it.enumerate() {
-            self.internal_deposit(
-                prev_owner_id,
-                &TokenType::MultiFungibleToken {
-                    account_id: account_id.clone(),
-                    subtoken_id: subtoken_id.to_string(),
-                },
-                amount.into(),
-            );
+            let token = TokenType::MultiFungibleToken {
+                account_id: account_id.clone(),
+                subtoken_id: subtoken_id.to_string(),
+            };
+            self.assert_can_deposit(&token, amount.into());
+            self.internal_record_deposit(&token, amount.into());
+            // actions run for the sender, so the sender is credited
+            let credit_id = match (&receiver_id, actions.is_empty()) {
+                (Some(receiver_id), _) => receiver_id,
+                (None, true) => prev_owner_id,
+                (None, false) => &sender_id,
+            };
+            self.internal_deposit(credit_id, &token, amount.into());
+            deposits.push((token, amount.into()));
             results.push(U128(0));
         }
+        if !actions.is_empty() {
+            self.internal_execute_transfer_actions(&sender_id, &deposits, actions);
+        }
         PromiseOrValue::Value(results)
     }
 }
@@ -2,6 +2,7 @@
 ///
 /// NB: fee tiers have been removed
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
 
 use crate::*;
 
@@ -11,7 +12,10 @@ pub const FEE_TO_BPS_DIVISOR: u128 = 10_000;
 pub const MAX_MAKER_REBATE_BOOST: u8 = 4;
 pub const MAX_TAKER_FEE_DISCOUNT: u8 = 5;
 
-#[derive(Copy, Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize)]
+#[derive(
+    Copy, Clone, Debug, PartialEq, BorshSerialize, BorshDeserialize, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
 #[repr(u8)]
 pub enum FeeTier {
     Base,
@@ -40,7 +44,7 @@ impl FeeCalculator {
         }
     }
 
-    pub fn new(account: &AccountV1, market: &Market) -> Self {
+    pub fn new(account: &Account, market: &Market) -> Self {
         Self {
             fee_tier: account.get_fee_tier(),
             taker_fee_base_rate: market.taker_fee_base_rate,
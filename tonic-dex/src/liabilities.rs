@@ -0,0 +1,266 @@
+/// Implements running per-token totals of what the contract owes users, and a
+/// check of those totals against the contract's token holdings.
+///
+/// Free and locked balances are tracked as deltas on each loaded account and
+/// applied to the totals when the account is saved, so changes discarded by a
+/// failed save are never counted. Fees are tracked when accrued and when paid
+/// out to creators.
+use std::collections::HashMap;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{ext_contract, serde_json, Gas, PromiseResult};
+
+use crate::*;
+
+/// 5 TGas
+pub const GAS_FOR_FT_BALANCE_OF: Gas = Gas(5_000_000_000_000);
+/// 5 TGas
+pub const GAS_FOR_RESOLVE_CHECK_RESERVES: Gas = Gas(5_000_000_000_000);
+
+#[ext_contract(ext_ft_balance)]
+pub trait FungibleTokenBalance {
+    fn ft_balance_of(&self, account_id: AccountId) -> U128;
+}
+
+#[ext_contract(ext_reserves)]
+pub trait ReservesCallback {
+    fn on_check_reserves(&mut self, token: AccountId) -> bool;
+}
+
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct Liabilities {
+    /// Total deposited into the contract, including swap inputs.
+    pub deposited: Balance,
+    /// Total sent out of the contract, net of reverted withdrawals.
+    pub withdrawn: Balance,
+    /// Free balances of all accounts.
+    pub balances: Balance,
+    /// Balances locked in open orders.
+    pub locked: Balance,
+    /// Protocol and creator fees accrued in markets quoted in the token.
+    pub fees: Balance,
+    /// Funds from failed withdrawals held for unregistered accounts.
+    pub unclaimed: Balance,
+}
+
+/// One of the totals in [Liabilities].
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum LiabilityField {
+    Deposited,
+    Withdrawn,
+    Balances,
+    Locked,
+    Fees,
+    Unclaimed,
+}
+
+impl Liabilities {
+    /// Amount the contract must hold to pay out every user and fee.
+    pub fn total(&self) -> Balance {
+        self.balances + self.locked + self.fees + self.unclaimed
+    }
+}
+
+/// Net changes to an account's free and locked balances since it was loaded,
+/// by token key.
+#[derive(Clone, Debug, Default)]
+pub struct BalanceDeltas(HashMap<String, (i128, i128)>);
+
+impl BalanceDeltas {
+    pub fn record_free(&mut self, token: &TokenType, delta: i128) {
+        self.0.entry(token.key()).or_default().0 += delta;
+    }
+
+    pub fn record_locked(&mut self, token: &TokenType, delta: i128) {
+        self.0.entry(token.key()).or_default().1 += delta;
+    }
+}
+
+/// Apply a change to one of a token's totals. A decrease larger than the total
+/// means the totals are off, eg, balances that predate tracking were withdrawn
+/// before a baseline was set. The total is then set to zero and a
+/// `liabilities_mismatch` event is logged, so the baseline can be corrected.
+fn apply_delta(token_key: &str, field: LiabilityField, total: Balance, delta: i128) -> Balance {
+    if delta >= 0 {
+        return _expect!(total.checked_add(delta as u128), "Liability total overflow");
+    }
+    match total.checked_sub(delta.unsigned_abs()) {
+        Some(total) => total,
+        None => {
+            emit_dex_event(DexEventType::LiabilitiesMismatch(
+                LiabilitiesMismatchEvent {
+                    token: token_key.to_string(),
+                    field,
+                    total: U128(total),
+                    decrease: U128(delta.unsigned_abs()),
+                },
+            ));
+            0
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Compare the contract's balance of a fungible token with its liabilities
+    /// in that token. Emits a `reserves_mismatch` event and returns false if
+    /// the balance is short. Only callable by the contract owner.
+    ///
+    /// Liabilities are read in the callback, so deposits and withdrawals in
+    /// flight between the two calls can cause a transient mismatch.
+    pub fn check_reserves(&mut self, token: AccountId) -> Promise {
+        self.assert_is_owner();
+        ext_ft_balance::ft_balance_of(
+            env::current_account_id(),
+            token.clone(),
+            0,
+            GAS_FOR_FT_BALANCE_OF,
+        )
+        .then(ext_reserves::on_check_reserves(
+            token,
+            env::current_account_id(),
+            0,
+            GAS_FOR_RESOLVE_CHECK_RESERVES,
+        ))
+    }
+
+    #[private]
+    pub fn on_check_reserves(&mut self, token: AccountId) -> bool {
+        _assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expected one promise result"
+        );
+        let balance: U128 = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                _expect!(
+                    serde_json::from_slice(&value).ok(),
+                    "Invalid ft_balance_of result"
+                )
+            }
+            _ => env::panic_str("Could not read token balance"),
+        };
+        let liabilities = self
+            .internal_get_liabilities(&TokenType::from_account_id(token.clone()).key())
+            .total();
+        if balance.0 >= liabilities {
+            return true;
+        }
+        emit_dex_event(DexEventType::ReservesMismatch(ReservesMismatchEvent {
+            token,
+            balance,
+            liabilities: U128(liabilities),
+        }));
+        false
+    }
+
+    /// Set the free, locked, and fee totals for a token, eg, to include
+    /// balances deposited before totals were tracked. Only callable by the
+    /// contract owner.
+    pub fn set_liabilities_baseline(
+        &mut self,
+        token: String,
+        balances: U128,
+        locked: U128,
+        fees: U128,
+    ) {
+        self.assert_is_owner();
+        let mut liabilities = self.internal_get_liabilities(&token);
+        liabilities.balances = balances.0;
+        liabilities.locked = locked.0;
+        liabilities.fees = fees.0;
+        self.liabilities.insert(&token, &liabilities);
+    }
+}
+
+impl Contract {
+    pub fn internal_get_liabilities(&self, token_key: &str) -> Liabilities {
+        self.liabilities
+            .get(&token_key.to_string())
+            .unwrap_or_default()
+    }
+
+    fn internal_update_liabilities(&mut self, token_key: &str, f: impl FnOnce(&mut Liabilities)) {
+        let mut liabilities = self.internal_get_liabilities(token_key);
+        f(&mut liabilities);
+        self.liabilities
+            .insert(&token_key.to_string(), &liabilities);
+    }
+
+    /// Apply an account's balance changes to the totals. Called when the
+    /// account is saved.
+    pub fn internal_apply_balance_deltas(&mut self, deltas: BalanceDeltas) {
+        for (token_key, (free, locked)) in deltas.0 {
+            if free == 0 && locked == 0 {
+                continue;
+            }
+            self.internal_update_liabilities(&token_key, |l| {
+                l.balances = apply_delta(&token_key, LiabilityField::Balances, l.balances, free);
+                l.locked = apply_delta(&token_key, LiabilityField::Locked, l.locked, locked);
+            });
+        }
+    }
+
+    pub fn internal_record_deposit(&mut self, token: &TokenType, amount: Balance) {
+        self.internal_update_liabilities(&token.key(), |l| l.deposited += amount);
+    }
+
+    /// Undo [Contract::internal_record_deposit] for an amount that was
+    /// refunded, eg, swap input that wasn't used.
+    pub fn internal_revert_deposit(&mut self, token: &TokenType, amount: Balance) {
+        let key = token.key();
+        self.internal_update_liabilities(&key, |l| {
+            l.deposited = apply_delta(
+                &key,
+                LiabilityField::Deposited,
+                l.deposited,
+                -(amount as i128),
+            )
+        });
+    }
+
+    pub fn internal_record_withdrawal(&mut self, token: &TokenType, amount: Balance) {
+        self.internal_update_liabilities(&token.key(), |l| l.withdrawn += amount);
+    }
+
+    /// Undo [Contract::internal_record_withdrawal] for a transfer that failed.
+    pub fn internal_revert_withdrawal(&mut self, token: &TokenType, amount: Balance) {
+        let key = token.key();
+        self.internal_update_liabilities(&key, |l| {
+            l.withdrawn = apply_delta(
+                &key,
+                LiabilityField::Withdrawn,
+                l.withdrawn,
+                -(amount as i128),
+            )
+        });
+    }
+
+    /// Accrue net taker fees on a market and add them to the quote token's
+    /// total.
+    pub fn internal_incr_fees_accrued(&mut self, market: &mut Market, amount: Balance) {
+        market.incr_fees_accrued(amount);
+        self.internal_update_liabilities(&market.quote_token.token_type.key(), |l| {
+            l.fees += amount
+        });
+    }
+
+    pub fn internal_record_unclaimed(&mut self, token: &TokenType, delta: i128) {
+        let key = token.key();
+        self.internal_update_liabilities(&key, |l| {
+            l.unclaimed = apply_delta(&key, LiabilityField::Unclaimed, l.unclaimed, delta)
+        });
+    }
+
+    /// Remove fees paid out of a market, eg, creator fees credited to an
+    /// account.
+    pub fn internal_decr_fees(&mut self, token: &TokenType, amount: Balance) {
+        let key = token.key();
+        self.internal_update_liabilities(&key, |l| {
+            l.fees = apply_delta(&key, LiabilityField::Fees, l.fees, -(amount as i128))
+        });
+    }
+}
@@ -11,6 +11,7 @@ use near_sdk::{
     assert_one_yocto, env, near_bindgen, require, AccountId, Balance, BorshStorageKey,
     PanicOnDefault, Promise, StorageUsage,
 };
+use std::collections::HashMap;
 
 pub use tonic_sdk::macros::*;
 pub use tonic_sdk::prelude::*;
@@ -19,33 +20,43 @@ mod account;
 mod actions;
 mod admin;
 mod balances;
+mod dex_events;
 mod external_tokens;
 mod fees;
+mod liabilities;
 mod limit_order;
 mod market;
 mod market_order;
+mod receiver_storage;
 mod settlement;
 mod storage;
 mod storage_manager;
 mod swap_order;
+mod token_registry;
+mod upgrade;
 mod views;
 
 pub use crate::account::*;
 pub use crate::actions::*;
 pub use crate::admin::*;
 pub use crate::balances::*;
+pub use crate::dex_events::*;
 pub use crate::external_tokens::*;
 pub use crate::external_tokens::*;
 pub use crate::fees::*;
+pub use crate::liabilities::*;
 pub use crate::limit_order::*;
 pub use crate::market::*;
 pub use crate::market_id::*;
 pub use crate::market_order::*;
 pub use crate::order_id::*;
+pub use crate::receiver_storage::*;
 pub use crate::settlement::*;
 pub use crate::storage::*;
 pub use crate::storage_manager::*;
 pub use crate::swap_order::*;
+pub use crate::token_registry::*;
+pub use crate::upgrade::*;
 pub use crate::views::*;
 
 #[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
@@ -80,12 +91,68 @@ pub struct Contract {
     pub prev_order_sequence_number: SequenceNumber,
 
     pub state: ContractState,
+
+    /// Registry of tokens that can be listed and deposited, keyed by token key.
+    pub tokens: LookupMap<String, TokenInfo>,
+
+    /// Used to enumerate registered tokens. See `market_iter_map`.
+    pub token_iter_map: Vector<String>,
+
+    /// Share of net taker fees paid to the creators of new markets, in bps.
+    pub creator_fee_share_bps: u32,
+
+    /// Last trade and rolling 24 hour stats per market.
+    pub market_stats: LookupMap<MarketId, MarketStats>,
+
+    /// Best bid and ask per market, cached from the orderbook.
+    pub top_of_book: LookupMap<MarketId, TopOfBook>,
+
+    /// State and tokens per market, copied from the market record so
+    /// summaries can be read without loading the orderbook.
+    pub market_headers: LookupMap<MarketId, MarketHeader>,
+
+    /// OHLCV candle buffers per market and resolution.
+    pub candles: LookupMap<(MarketId, CandleResolution), CandleBuffer>,
+
+    /// TWAP accumulators per market.
+    pub oracles: LookupMap<MarketId, PriceOracle>,
+
+    /// Totals owed to users and fee recipients, by token key.
+    pub liabilities: LookupMap<String, Liabilities>,
+
+    /// Funds from failed withdrawals and payouts to accounts that no longer
+    /// exist, by account. Entries are paid for from the registration pool.
+    pub unclaimed: LookupMap<AccountId, UnclaimedBalances>,
+
+    /// NEAR attached ahead of a first token deposit, used to register the
+    /// account when the deposit arrives.
+    pub storage_prepayments: LookupMap<AccountId, Balance>,
+
+    /// NEAR set aside to register accounts on their first token deposit when
+    /// they haven't prepaid.
+    pub registration_pool: Balance,
+
+    /// Market IDs by upper-cased ticker symbol, eg, "NEAR/USDC". Different
+    /// markets can share a symbol.
+    pub markets_by_symbol: LookupMap<String, Vec<MarketId>>,
+
+    /// Market IDs by base and quote token keys.
+    pub markets_by_pair: LookupMap<String, Vec<MarketId>>,
+
+    /// Market IDs by token key, for both base and quote tokens.
+    pub markets_by_token: LookupMap<String, Vec<MarketId>>,
+
+    /// Position in `market_iter_map` of the next market to migrate after an
+    /// upgrade from version 1 state. None when there's nothing left to
+    /// migrate. See `migrate_markets`.
+    pub market_migration_cursor: Option<u64>,
 }
 
 #[near_bindgen]
 impl Contract {
     #[init]
     pub fn new(owner_id: AccountId) -> Self {
+        write_state_version();
         Self {
             owner_id,
             accounts: LookupMap::new(StorageKey::Accounts),
@@ -93,6 +160,22 @@ impl Contract {
             market_iter_map: Vector::new(StorageKey::MarketIterMap),
             prev_order_sequence_number: 0,
             state: ContractState::Active,
+            tokens: LookupMap::new(StorageKey::Tokens),
+            token_iter_map: Vector::new(StorageKey::TokenIterMap),
+            creator_fee_share_bps: 0,
+            market_stats: LookupMap::new(StorageKey::MarketStats),
+            top_of_book: LookupMap::new(StorageKey::TopOfBook),
+            market_headers: LookupMap::new(StorageKey::MarketHeaders),
+            candles: LookupMap::new(StorageKey::Candles),
+            oracles: LookupMap::new(StorageKey::Oracles),
+            liabilities: LookupMap::new(StorageKey::Liabilities),
+            unclaimed: LookupMap::new(StorageKey::Unclaimed),
+            storage_prepayments: LookupMap::new(StorageKey::StoragePrepayments),
+            registration_pool: 0,
+            markets_by_symbol: LookupMap::new(StorageKey::MarketsBySymbol),
+            markets_by_pair: LookupMap::new(StorageKey::MarketsByPair),
+            markets_by_token: LookupMap::new(StorageKey::MarketsByToken),
+            market_migration_cursor: None,
         }
     }
 
@@ -130,4 +213,14 @@ impl Contract {
             "Contract must be active or cancel only to cancel an order"
         );
     }
+
+    /// Orders can't be placed until `migrate_markets` has converted and
+    /// reindexed every market. Cancels, deposits and withdrawals work during
+    /// the migration.
+    pub fn assert_markets_migrated(&self) {
+        _assert!(
+            self.market_migration_cursor.is_none(),
+            "Markets are still being migrated"
+        );
+    }
 }
@@ -34,7 +34,7 @@ impl Contract {
         &mut self,
         market: &mut Market,
         taker_account_id: AccountId,
-        taker_account: &mut AccountV1,
+        taker_account: &mut Account,
         params: NewOrderParams,
     ) -> PlaceOrderResult {
         let fee_calculator = market.fee_calculator(taker_account);
@@ -96,6 +96,7 @@ impl Contract {
             base_traded,
             quote_traded,
             total_maker_rebate,
+            orderbook_storage_released,
         } = self.internal_settle_maker_fills(
             market,
             result.id,
@@ -114,7 +115,10 @@ impl Contract {
             total_taker_fee >= referrer_rebate + total_maker_rebate,
             "bid accounting bug: over-counted fees"
         );
-        market.incr_fees_accrued(total_taker_fee - total_maker_rebate - referrer_rebate);
+        self.internal_incr_fees_accrued(
+            market,
+            total_taker_fee - total_maker_rebate - referrer_rebate,
+        );
 
         // Settle taker balance changes. Start by crediting any base purchased
         // due to crossing the spread, then debit the amount of quote spent and
@@ -132,9 +136,11 @@ impl Contract {
         let total_quote_debit = quote_locked + quote_traded + total_taker_fee;
         _assert!(total_quote_debit <= max_quote_debit, "bid bug: overspent");
         taker_account.withdraw(&market.quote_token.token_type, total_quote_debit);
+        taker_account.lock(&market.quote_token.token_type, quote_locked);
 
         // Save the taker's newly posted order on their account
         if result.is_posted() {
+            taker_account.offset_orderbook_storage(orderbook_storage_released);
             taker_account.save_new_order_info(
                 &market.unwrap_id().clone(),
                 result.id,
@@ -177,7 +183,7 @@ impl Contract {
         &mut self,
         market: &mut Market,
         taker_account_id: AccountId,
-        taker_account: &mut AccountV1,
+        taker_account: &mut Account,
         params: NewOrderParams,
     ) -> PlaceOrderResult {
         let fee_calculator = market.fee_calculator(taker_account);
@@ -225,6 +231,7 @@ impl Contract {
             quote_traded,
             base_traded,
             total_maker_rebate,
+            orderbook_storage_released,
         } = self.internal_settle_maker_fills(
             market,
             result.id,
@@ -246,7 +253,10 @@ impl Contract {
             total_taker_fee >= referrer_rebate + total_maker_rebate,
             "ask accounting bug: over-counted fees"
         );
-        market.incr_fees_accrued(total_taker_fee - total_maker_rebate - referrer_rebate);
+        self.internal_incr_fees_accrued(
+            market,
+            total_taker_fee - total_maker_rebate - referrer_rebate,
+        );
 
         // Settle taker balance changes. Start by crediting taker with quote
         // from crossing the spread, then debit amount of base sold and amount
@@ -263,9 +273,11 @@ impl Contract {
         let total_base_debit = base_traded + base_locked;
         _assert!(total_base_debit <= max_base_debit, "ask bug: oversold");
         taker_account.withdraw(&market.base_token.token_type, total_base_debit);
+        taker_account.lock(&market.base_token.token_type, base_locked);
 
         // Save the taker's newly posted order on their account
         if result.is_posted() {
+            taker_account.offset_orderbook_storage(orderbook_storage_released);
             taker_account.save_new_order_info(
                 &market.unwrap_id().clone(),
                 result.id,
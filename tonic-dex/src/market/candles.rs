@@ -0,0 +1,149 @@
+/// Implements on-chain OHLCV candles. Each market keeps a fixed-size ring
+/// buffer per resolution, allocated when the market is created so that the
+/// storage is paid from the market's deposit.
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::Timestamp;
+
+use crate::*;
+
+#[derive(
+    Copy, Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum CandleResolution {
+    OneMinute,
+    OneHour,
+}
+
+impl CandleResolution {
+    pub const ALL: [CandleResolution; 2] = [CandleResolution::OneMinute, CandleResolution::OneHour];
+
+    pub fn duration_ns(&self) -> u64 {
+        match self {
+            CandleResolution::OneMinute => 60 * 1_000_000_000,
+            CandleResolution::OneHour => 3_600 * 1_000_000_000,
+        }
+    }
+
+    /// Number of candles kept, ie, 1 hour of 1-minute candles and 1 day of
+    /// 1-hour candles.
+    pub fn capacity(&self) -> usize {
+        match self {
+            CandleResolution::OneMinute => 60,
+            CandleResolution::OneHour => 24,
+        }
+    }
+}
+
+/// Prices and volume are stored in lots to keep the buffers small.
+#[derive(Clone, Debug, Default, BorshDeserialize, BorshSerialize)]
+pub struct Candle {
+    /// Start of the period, in nanoseconds since the unix epoch.
+    pub start: Timestamp,
+    pub open: LotBalance,
+    pub high: LotBalance,
+    pub low: LotBalance,
+    pub close: LotBalance,
+    /// Base traded. Zero for unused slots.
+    pub volume: LotBalance,
+}
+
+#[derive(Clone, Debug, BorshDeserialize, BorshSerialize)]
+pub struct CandleBuffer {
+    pub resolution: CandleResolution,
+    /// Candles indexed by period number modulo capacity.
+    pub candles: Vec<Candle>,
+}
+
+impl CandleBuffer {
+    pub fn new(resolution: CandleResolution) -> Self {
+        Self {
+            resolution,
+            candles: vec![Candle::default(); resolution.capacity()],
+        }
+    }
+
+    pub fn record_trade(&mut self, price: LotBalance, quantity: LotBalance, timestamp: Timestamp) {
+        let duration = self.resolution.duration_ns();
+        let period = timestamp / duration;
+        let capacity = self.candles.len() as u64;
+        let candle = &mut self.candles[(period % capacity) as usize];
+        let start = period * duration;
+        if candle.start != start || candle.volume == 0 {
+            *candle = Candle {
+                start,
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume: 0,
+            };
+        }
+        candle.high = candle.high.max(price);
+        candle.low = candle.low.min(price);
+        candle.close = price;
+        candle.volume += quantity;
+    }
+
+    /// Candles starting at or after `from`, oldest first. Periods without
+    /// trades are skipped rather than filled in.
+    pub fn candles_from(&self, from: Timestamp) -> Vec<Candle> {
+        let mut ret: Vec<Candle> = self
+            .candles
+            .iter()
+            .filter(|c| c.volume > 0 && c.start >= from)
+            .cloned()
+            .collect();
+        ret.sort_by_key(|c| c.start);
+        ret
+    }
+}
+
+impl Contract {
+    /// Allocate empty candle buffers for a new market.
+    pub fn internal_init_candles(&mut self, market_id: &MarketId) {
+        for resolution in CandleResolution::ALL {
+            self.candles
+                .insert(&(*market_id, resolution), &CandleBuffer::new(resolution));
+        }
+    }
+
+    pub fn internal_remove_candles(&mut self, market_id: &MarketId) {
+        for resolution in CandleResolution::ALL {
+            self.candles.remove(&(*market_id, resolution));
+        }
+    }
+
+    /// Markets created before candles existed get their buffers on the first
+    /// trade, paid for by the contract.
+    pub fn internal_get_candles(
+        &self,
+        market_id: &MarketId,
+        resolution: CandleResolution,
+    ) -> CandleBuffer {
+        self.candles
+            .get(&(*market_id, resolution))
+            .unwrap_or_else(|| CandleBuffer::new(resolution))
+    }
+
+    /// Add fills to all of a market's candle buffers. Fills are `(price lots,
+    /// quantity lots)`.
+    pub fn internal_record_candles(
+        &mut self,
+        market_id: &MarketId,
+        fills: &[(LotBalance, LotBalance)],
+    ) {
+        if fills.is_empty() {
+            return;
+        }
+        let now = env::block_timestamp();
+        for resolution in CandleResolution::ALL {
+            let mut buffer = self.internal_get_candles(market_id, resolution);
+            for (price, quantity) in fills {
+                buffer.record_trade(*price, *quantity, now);
+            }
+            self.candles.insert(&(*market_id, resolution), &buffer);
+        }
+    }
+}
@@ -0,0 +1,165 @@
+/// Indexes for finding markets without knowing their IDs, eg, by ticker symbol
+/// or by token pair.
+use near_sdk::collections::LookupMap;
+
+use crate::*;
+
+/// Symbols are matched case-insensitively.
+pub fn symbol_index_key(symbol: &str) -> String {
+    symbol.to_uppercase()
+}
+
+pub fn token_index_key(token: &TokenType) -> String {
+    token.key()
+}
+
+pub fn pair_index_key(base_token: &TokenType, quote_token: &TokenType) -> String {
+    format!("{} {}", base_token.key(), quote_token.key())
+}
+
+fn index_insert(index: &mut LookupMap<String, Vec<MarketId>>, key: String, market_id: MarketId) {
+    let mut market_ids = index.get(&key).unwrap_or_default();
+    if !market_ids.contains(&market_id) {
+        market_ids.push(market_id);
+        index.insert(&key, &market_ids);
+    }
+}
+
+fn index_remove(index: &mut LookupMap<String, Vec<MarketId>>, key: String, market_id: MarketId) {
+    if let Some(mut market_ids) = index.get(&key) {
+        market_ids.retain(|id| *id != market_id);
+        if market_ids.is_empty() {
+            index.remove(&key);
+        } else {
+            index.insert(&key, &market_ids);
+        }
+    }
+}
+
+impl Contract {
+    /// Add a new market to the lookup indexes. Takes the ID separately since
+    /// it isn't initialized on markets that haven't been saved yet.
+    pub fn internal_index_market(&mut self, market_id: &MarketId, market: &Market) {
+        index_insert(
+            &mut self.markets_by_pair,
+            pair_index_key(
+                &market.base_token.token_type,
+                &market.quote_token.token_type,
+            ),
+            *market_id,
+        );
+        for token in [&market.base_token, &market.quote_token] {
+            index_insert(
+                &mut self.markets_by_token,
+                token_index_key(&token.token_type),
+                *market_id,
+            );
+        }
+        if let Some(symbol) = market.symbol() {
+            index_insert(
+                &mut self.markets_by_symbol,
+                symbol_index_key(&symbol),
+                *market_id,
+            );
+        }
+    }
+
+    pub fn internal_unindex_market(&mut self, market_id: &MarketId, market: &Market) {
+        index_remove(
+            &mut self.markets_by_pair,
+            pair_index_key(
+                &market.base_token.token_type,
+                &market.quote_token.token_type,
+            ),
+            *market_id,
+        );
+        for token in [&market.base_token, &market.quote_token] {
+            index_remove(
+                &mut self.markets_by_token,
+                token_index_key(&token.token_type),
+                *market_id,
+            );
+        }
+        if let Some(symbol) = market.symbol() {
+            index_remove(
+                &mut self.markets_by_symbol,
+                symbol_index_key(&symbol),
+                *market_id,
+            );
+        }
+    }
+
+    /// Set one of a market's display symbols and move the market to its new
+    /// entry in the symbol index. Does not save the market.
+    pub fn internal_set_market_symbol(
+        &mut self,
+        market: &mut Market,
+        side: &PairSide,
+        symbol: Option<String>,
+    ) {
+        let market_id = market.unwrap_id();
+        if let Some(old_symbol) = market.symbol() {
+            index_remove(
+                &mut self.markets_by_symbol,
+                symbol_index_key(&old_symbol),
+                market_id,
+            );
+        }
+        match side {
+            PairSide::Base => market.base_symbol = symbol,
+            PairSide::Quote => market.quote_symbol = symbol,
+        }
+        if let Some(new_symbol) = market.symbol() {
+            index_insert(
+                &mut self.markets_by_symbol,
+                symbol_index_key(&new_symbol),
+                market_id,
+            );
+        }
+    }
+
+    pub fn internal_find_markets_by_symbol(&self, symbol: &str) -> Vec<MarketId> {
+        self.markets_by_symbol
+            .get(&symbol_index_key(symbol))
+            .unwrap_or_default()
+    }
+
+    pub fn internal_find_markets_by_pair(
+        &self,
+        base_token: &TokenType,
+        quote_token: &TokenType,
+    ) -> Vec<MarketId> {
+        self.markets_by_pair
+            .get(&pair_index_key(base_token, quote_token))
+            .unwrap_or_default()
+    }
+
+    /// Markets listing the token as either base or quote.
+    pub fn internal_find_markets_by_token(&self, token: &TokenType) -> Vec<MarketId> {
+        self.markets_by_token
+            .get(&token_index_key(token))
+            .unwrap_or_default()
+    }
+
+    /// Summaries of markets matching all given filters, using the narrowest
+    /// index available for the token filters.
+    pub fn internal_find_markets(
+        &self,
+        base_token: Option<&TokenType>,
+        quote_token: Option<&TokenType>,
+        state: Option<MarketState>,
+    ) -> Vec<MarketSummary> {
+        let candidates = match (base_token, quote_token) {
+            (Some(base), Some(quote)) => self.internal_find_markets_by_pair(base, quote),
+            (Some(token), None) | (None, Some(token)) => self.internal_find_markets_by_token(token),
+            (None, None) => self.market_iter_map.to_vec(),
+        };
+        candidates
+            .iter()
+            .filter_map(|id| self.internal_get_market_summary(id))
+            .filter(|m| base_token.map_or(true, |t| m.base_token.token_type.key() == t.key()))
+            .filter(|m| quote_token.map_or(true, |t| m.quote_token.token_type.key() == t.key()))
+            .filter(|m| state.map_or(true, |s| m.state == s))
+            .collect()
+    }
+}
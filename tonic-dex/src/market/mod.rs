@@ -1,11 +1,22 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::IntoStorageKey;
 
 use tonic_sdk::{measure_gas, orderbook::CancelOrderResult};
 
 use crate::*;
 
+pub mod candles;
+pub mod index;
+pub mod oracle;
+pub mod ticker;
 pub mod v1;
+pub mod v2;
+pub use candles::*;
+pub use index::*;
+pub use oracle::*;
+pub use ticker::*;
 pub use v1::*;
+pub use v2::*;
 
 /// Market creation depends on a promise chain. This is used as a default value
 /// while waiting for the data to come back.
@@ -15,14 +26,19 @@ pub const DEFAULT_MAX_ORDERS: u8 = 20;
 pub const DEFAULT_MIN_MULTIPLIER_BPS: u32 = 1_000; // 10%
 pub const DEFAULT_MAX_MULTIPLIER_BPS: u32 = 300_000; // 3000%
 
+/// Versioned market record. Like accounts, markets are migrated lazily: older
+/// variants are converted when read and written back as [VMarket::Current] the
+/// next time the market is saved.
 #[derive(BorshDeserialize, BorshSerialize)]
 pub enum VMarket {
+    V1(MarketV1),
     Current(Market),
 }
 
 impl From<VMarket> for Market {
     fn from(v: VMarket) -> Self {
         match v {
+            VMarket::V1(m) => m.into(),
             VMarket::Current(a) => a,
         }
     }
@@ -34,6 +50,72 @@ impl From<Market> for VMarket {
     }
 }
 
+/// Market parameters that can be read without loading the orderbook.
+#[derive(Clone, Debug)]
+pub struct MarketSummary {
+    pub id: MarketId,
+    pub state: MarketState,
+    pub base_token: Token,
+    pub quote_token: Token,
+}
+
+impl MarketSummary {
+    pub fn quote_lots_to_native(&self, lots: LotBalance) -> Balance {
+        lots as u128 * self.quote_token.lot_size as u128
+    }
+
+    pub fn base_lots_to_native(&self, lots: LotBalance) -> Balance {
+        lots as u128 * self.base_token.lot_size as u128
+    }
+
+    /// See [Market::notional].
+    pub fn notional(&self, price: Balance, quantity: Balance) -> Balance {
+        (U256::from(quantity) * U256::from(price) / denomination(self.base_token.decimals))
+            .as_u128()
+    }
+}
+
+/// State and tokens of a market, stored under `market_headers` whenever they
+/// change. Every [VMarket] variant also serializes these first, before the
+/// orderbook, so they can be decoded from the raw record of a market that
+/// hasn't been migrated yet.
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct MarketHeader {
+    pub state: MarketState,
+    pub base_token: Token,
+    pub quote_token: Token,
+}
+
+impl From<&Market> for MarketHeader {
+    fn from(market: &Market) -> Self {
+        Self {
+            state: market.state,
+            base_token: market.base_token.clone(),
+            quote_token: market.quote_token.clone(),
+        }
+    }
+}
+
+impl MarketHeader {
+    fn into_summary(self, id: MarketId) -> MarketSummary {
+        MarketSummary {
+            id,
+            state: self.state,
+            base_token: self.base_token,
+            quote_token: self.quote_token,
+        }
+    }
+}
+
+fn read_market_record(id: &MarketId) -> Option<Vec<u8>> {
+    let key = [
+        StorageKey::Markets.into_storage_key(),
+        id.try_to_vec().unwrap(),
+    ]
+    .concat();
+    env::storage_read(&key)
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct CreateMarketArgs {
@@ -64,8 +146,10 @@ fn gen_market_id(
 
 #[near_bindgen]
 impl Contract {
-    /// Create a market. Since markets can never be deleted, storage deposits
-    /// for markets cannot be recovered.
+    /// Create a market. Both tokens must be allowed in the token registry. The
+    /// caller is recorded as the market creator: they earn a share of the
+    /// market's fees and get the storage deposit back if the market is
+    /// deleted.
     #[payable]
     pub fn create_market(&mut self, args: CreateMarketArgs) -> MarketId {
         self.assert_active();
@@ -83,6 +167,8 @@ impl Contract {
 
         let base_token = TokenType::from_key(&args.base_token);
         let quote_token = TokenType::from_key(&args.quote_token);
+        let base_token_info = self.assert_token_listable(&base_token);
+        let quote_token_info = self.assert_token_listable(&quote_token);
         let market_id = gen_market_id(
             base_token.clone(),
             args.base_token_lot_size.0,
@@ -94,41 +180,64 @@ impl Contract {
         }
 
         self.assert_valid_lot_sizes(args.base_token_lot_size.0, args.quote_token_lot_size.0);
-        // If either token is native NEAR, we know it has 24 decimals. If not,
-        // use an invalid default as a placeholder and set the token's decimals
-        // in ft_metadata callback.
-        let base_decimals = self
-            .get_decimals_for_token(&market_id, PairSide::Base, &base_token)
+        // Use decimals from the token registry if we have them. If not, use an
+        // invalid default as a placeholder and set the token's decimals in the
+        // metadata callback.
+        let base_decimals = base_token_info
+            .decimals
+            .or_else(|| self.get_decimals_for_token(&market_id, PairSide::Base, &base_token))
             .unwrap_or(INVALID_DECIMALS);
-        let quote_decimals = self
-            .get_decimals_for_token(&market_id, PairSide::Quote, &quote_token)
+        let quote_decimals = quote_token_info
+            .decimals
+            .or_else(|| self.get_decimals_for_token(&market_id, PairSide::Quote, &quote_token))
             .unwrap_or(INVALID_DECIMALS);
 
-        let storage_increase = measure_storage_increase!({
-            self.internal_save_market(
-                &market_id,
-                Market {
-                    id: None,
-                    base_token: Token {
-                        token_type: base_token.clone(),
-                        lot_size: args.base_token_lot_size.0,
-                        decimals: base_decimals,
-                    },
-                    quote_token: Token {
-                        token_type: quote_token.clone(),
-                        lot_size: args.quote_token_lot_size.0,
-                        decimals: quote_decimals,
-                    },
-                    orderbook: Orderbook::default(),
-                    state: MarketState::Uninitialized,
-                    fees_accrued: 0,
-                    taker_fee_base_rate: args.taker_fee_base_rate,
-                    maker_rebate_base_rate: args.maker_rebate_base_rate,
-                    max_orders_per_account: DEFAULT_MAX_ORDERS,
-                    minimum_bid_bps: DEFAULT_MIN_MULTIPLIER_BPS,
-                    maximum_ask_bps: DEFAULT_MAX_MULTIPLIER_BPS,
-                },
+        let mut market = Market {
+            id: None,
+            base_token: Token {
+                token_type: base_token.clone(),
+                lot_size: args.base_token_lot_size.0,
+                decimals: base_decimals,
+            },
+            quote_token: Token {
+                token_type: quote_token.clone(),
+                lot_size: args.quote_token_lot_size.0,
+                decimals: quote_decimals,
+            },
+            orderbook: Orderbook::default(),
+            state: MarketState::Uninitialized,
+            fees_accrued: 0,
+            taker_fee_base_rate: args.taker_fee_base_rate,
+            maker_rebate_base_rate: args.maker_rebate_base_rate,
+            max_orders_per_account: DEFAULT_MAX_ORDERS,
+            minimum_bid_bps: DEFAULT_MIN_MULTIPLIER_BPS,
+            maximum_ask_bps: DEFAULT_MAX_MULTIPLIER_BPS,
+            creator_id: Some(env::predecessor_account_id()),
+            creator_fee_share_bps: self.creator_fee_share_bps,
+            creator_fees_accrued: 0,
+            storage_deposit: 0,
+            base_symbol: base_token_info.symbol,
+            quote_symbol: quote_token_info.symbol,
+            tick_size: args.quote_token_lot_size.0,
+            verified: false,
+            state_set_by_owner: false,
+        };
+        // When both decimals are already known, there's no callback to wait
+        // for, so the market can start trading immediately.
+        if base_decimals != INVALID_DECIMALS && quote_decimals != INVALID_DECIMALS {
+            _assert!(
+                market.validate_lots_and_decimals(),
+                "Invalid lot sizes for token decimals"
             );
+            market.state = MarketState::Active;
+        }
+
+        let storage_increase = measure_storage_increase!({
+            self.internal_index_market(&market_id, &market);
+            self.internal_save_market_stats(&market_id, &MarketStats::default());
+            self.internal_init_candles(&market_id);
+            self.oracles.insert(&market_id, &PriceOracle::default());
+            self.internal_save_market(&market_id, market);
             self.market_iter_map.push(&market_id);
         });
 
@@ -142,6 +251,12 @@ impl Contract {
             Promise::new(env::predecessor_account_id()).transfer(refund);
         }
 
+        // Record the deposit so it can be refunded to the creator if the
+        // market is deleted. This doesn't change the size of the market.
+        let mut market = self.internal_unwrap_market(&market_id);
+        market.storage_deposit = deposit_used;
+        self.internal_save_market(&market_id, market);
+
         emit_event(EventType::NewMarket(NewMarketEvent {
             creator_id: env::predecessor_account_id(),
             market_id,
@@ -176,13 +291,30 @@ impl Contract {
         &mut self,
         market_id: MarketId,
         order: NewOrderParams,
+    ) -> PlaceOrderResultView {
+        self.internal_new_order(&market_id, env::predecessor_account_id(), order)
+    }
+}
+
+impl Contract {
+    /// Place a new order for `taker_account_id`.
+    pub fn internal_new_order(
+        &mut self,
+        market_id: &MarketId,
+        taker_account_id: AccountId,
+        order: NewOrderParams,
     ) -> PlaceOrderResultView {
         self.assert_active();
+        self.assert_markets_migrated();
         self.assert_valid_order(&order);
-        let mut market = self.internal_unwrap_market(&market_id);
+        let mut market = self.internal_unwrap_market(market_id);
         market.assert_active();
+        if order.order_type != OrderType::Market {
+            if let Some(limit_price) = order.limit_price {
+                market.assert_valid_price(limit_price.0);
+            }
+        }
 
-        let taker_account_id = env::predecessor_account_id();
         let mut taker_account = self.internal_unwrap_account(&taker_account_id);
 
         let result = if order.order_type == OrderType::Market {
@@ -209,17 +341,31 @@ impl Contract {
             }
         };
 
+        let is_posted = result.is_posted();
         let ret = result.into_view(market.base_token.lot_size, market.quote_token.lot_size);
 
+        // Charge the taker for the orderbook storage used by their posted
+        // order. The change is signed, since fills remove maker orders.
+        let storage_usage_before = env::storage_usage();
+        self.internal_save_market(market_id, market);
+        if is_posted {
+            taker_account.charge_orderbook_storage(
+                env::storage_usage() as i64 - storage_usage_before as i64,
+            );
+        }
         self.internal_save_account(&taker_account_id, taker_account);
-        self.internal_save_market(&market_id, market);
+        #[cfg(feature = "expensive_debug")]
+        self.internal_assert_locked_balances(&self.internal_unwrap_account(&taker_account_id));
 
         ret
     }
-}
 
-impl Contract {
     pub fn internal_save_market(&mut self, id: &MarketId, market: Market) {
+        self.internal_update_top_of_book(id, &market);
+        let header = MarketHeader::from(&market);
+        if self.market_headers.get(id).as_ref() != Some(&header) {
+            self.market_headers.insert(id, &header);
+        }
         self.markets.insert(id, &market.into());
     }
 
@@ -237,6 +383,32 @@ impl Contract {
         self.internal_get_market(id).unwrap()
     }
 
+    /// Read a market's summary from its header. Markets `migrate_markets`
+    /// hasn't reached yet fall back to the prefix of the market record.
+    pub fn internal_get_market_summary(&self, id: &MarketId) -> Option<MarketSummary> {
+        self.market_headers
+            .get(id)
+            .or_else(|| {
+                read_market_record(id).map(|raw| {
+                    // skip the VMarket variant tag
+                    MarketHeader::deserialize(&mut &raw[1..]).unwrap()
+                })
+            })
+            .map(|header| header.into_summary(*id))
+    }
+
+    /// Load a market's summary and orderbook without converting the rest of
+    /// the record. Every [VMarket] variant stores the orderbook right after
+    /// the summary fields.
+    pub fn internal_get_orderbook(&self, id: &MarketId) -> Option<(MarketSummary, VecOrderbook)> {
+        read_market_record(id).map(|raw| {
+            let mut buf = &raw[1..];
+            let header = MarketHeader::deserialize(&mut buf).unwrap();
+            let orderbook = VecOrderbook::deserialize(&mut buf).unwrap();
+            (header.into_summary(*id), orderbook)
+        })
+    }
+
     // NB: no longer used; invalid/disabled markets are periodically
     // swept with admin_delete_market
     // pub fn internal_remove_market(&mut self, id: &MarketId) {
@@ -403,7 +575,7 @@ impl Contract {
 /// Credit the account for any open orders. Does not save the account yet.
 fn process_refunds(
     market: &Market,
-    account: &mut AccountV1,
+    account: &mut Account,
     pending: Vec<CancelOrderResult>,
 ) -> Vec<CancelEventData> {
     let mut cancels: Vec<CancelEventData> = vec![];
@@ -422,6 +594,7 @@ fn process_refunds(
         );
         let (refund_amount, token) = get_refund_amount(market, &order);
         account.deposit(&token, refund_amount);
+        account.unlock(&token, refund_amount);
         account.remove_order_info(&market.unwrap_id(), order.id());
 
         cancels.push(CancelEventData {
@@ -438,26 +611,12 @@ fn process_refunds(
     cancels
 }
 
-/// Return the amount and token type to refund after cancelling the order.
-fn get_refund_amount(market: &Market, order: &OpenLimitOrder) -> (Balance, TokenType) {
-    match order.unwrap_side() {
-        Side::Buy => {
-            let base_denomination = market.base_denomination();
-            let refund_amount = ({
-                U256::from(order.open_qty_lots)
-                    * U256::from(order.unwrap_price())
-                    * U256::from(market.quote_token.lot_size)
-                    * U256::from(market.base_token.lot_size)
-                    / U256::from(base_denomination)
-            })
-            .as_u128();
-            (refund_amount, market.quote_token.token_type.clone())
-        }
-        Side::Sell => (
-            (order.open_qty_lots as u128)
-                .checked_mul(market.base_token.lot_size)
-                .unwrap(),
-            market.base_token.token_type.clone(),
-        ),
-    }
+/// Return the amount and token type to refund after cancelling the order, ie,
+/// the balance locked in the order.
+pub(crate) fn get_refund_amount(market: &Market, order: &OpenLimitOrder) -> (Balance, TokenType) {
+    market.locked_for_order(
+        order.unwrap_side(),
+        order.unwrap_price(),
+        order.open_qty_lots,
+    )
 }
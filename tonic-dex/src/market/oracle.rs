@@ -0,0 +1,225 @@
+/// Implements a time-weighted average price oracle, in the style of Uniswap
+/// v2's cumulative price accumulators.
+///
+/// Each market tracks the sum of `price * time` since its first trade. The
+/// price used for an interval is the last trade price at the *end of the
+/// previous block*: the accumulator is only advanced when time has passed, ie,
+/// on the first trade of a new block, before that trade's price is recorded.
+/// The TWAP over a window is the difference between two accumulator readings
+/// divided by the time between them.
+///
+/// The price is always the last trade price. Accumulators are advanced by
+/// trades and by any other order activity that moves the top of the book, so
+/// observations keep being taken while a market is quoted but not trading.
+/// Without trades the price doesn't change, and reads extrapolate the
+/// accumulator from the last update to the current time; `staleness` reports
+/// the time since the last trade, not the last update. Each observation
+/// records the price in effect since the last trade before it, so a window
+/// that starts in a gap between trades is read exactly rather than stretched
+/// back to the previous observation.
+///
+/// Manipulation resistance:
+/// * Trades that move the price and move it back within one block have no
+///   effect on the accumulator.
+/// * To shift a TWAP over window `W` by `d`, a manipulator has to hold the last
+///   trade price off by `d * W / t` for `t` nanoseconds, leaving resting
+///   liquidity at the manipulated price open to arbitrage for the whole time.
+///   Longer windows are more expensive to move.
+/// * Markets with little liquidity or few trades are cheaper to move. Callers
+///   should check `confidence` (half the spread) and `staleness` (time since
+///   the last trade) and reject prices outside their tolerance.
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::Timestamp;
+
+use crate::*;
+
+/// Observations of the accumulator are taken at most this often.
+pub const OBSERVATION_INTERVAL_NS: u64 = 60 * 1_000_000_000;
+
+/// With one observation a minute, windows up to an hour are always covered
+/// while the market trades or its top of book moves at least once a minute.
+pub const MAX_OBSERVATIONS: usize = 60;
+
+/// Window used for the price in [Contract::get_prices].
+pub const DEFAULT_TWAP_WINDOW_NS: u64 = 5 * 60 * 1_000_000_000;
+
+#[derive(Clone, Copy, Debug, Default, BorshDeserialize, BorshSerialize)]
+pub struct Observation {
+    pub timestamp: Timestamp,
+    pub price_cumulative: u128,
+
+    /// Price in lots in effect from `price_since` until `timestamp`.
+    pub price: LotBalance,
+    pub price_since: Timestamp,
+}
+
+#[derive(Clone, Debug, BorshDeserialize, BorshSerialize)]
+pub struct PriceOracle {
+    /// Last trade price in quote lots. Zero until the first trade.
+    pub last_price: LotBalance,
+    pub last_trade: Timestamp,
+
+    /// Time the accumulator was last advanced.
+    pub last_update: Timestamp,
+
+    /// Sum of price lots * nanoseconds. Wraps on overflow; differences between
+    /// readings are still correct.
+    pub price_cumulative: u128,
+
+    /// Ring buffer of accumulator readings, allocated up front.
+    pub observations: Vec<Observation>,
+    pub observation_count: u32,
+    pub next_observation: u32,
+}
+
+impl Default for PriceOracle {
+    fn default() -> Self {
+        Self {
+            last_price: 0,
+            last_trade: 0,
+            last_update: 0,
+            price_cumulative: 0,
+            observations: vec![Observation::default(); MAX_OBSERVATIONS],
+            observation_count: 0,
+            next_observation: 0,
+        }
+    }
+}
+
+impl PriceOracle {
+    /// Accumulator value at `now`, assuming no trades since the last update.
+    pub fn price_cumulative_at(&self, now: Timestamp) -> u128 {
+        let elapsed = now.saturating_sub(self.last_update) as u128;
+        self.price_cumulative
+            .wrapping_add(self.last_price as u128 * elapsed)
+    }
+
+    /// Whether an observation would be taken at `now`.
+    pub fn observation_due(&self, now: Timestamp) -> bool {
+        self.observation_count == 0
+            || now >= self.newest_observation().timestamp + OBSERVATION_INTERVAL_NS
+    }
+
+    /// Take an observation, if one is due, after the accumulator has been
+    /// advanced to `now` and before the new trade price is recorded.
+    fn observe(&mut self, now: Timestamp, price_since: Timestamp) {
+        if !self.observation_due(now) {
+            return;
+        }
+        self.observations[self.next_observation as usize] = Observation {
+            timestamp: now,
+            price_cumulative: self.price_cumulative,
+            price: self.last_price,
+            price_since,
+        };
+        self.next_observation = (self.next_observation + 1) % self.observations.len() as u32;
+        self.observation_count = (self.observation_count + 1).min(self.observations.len() as u32);
+    }
+
+    fn newest_observation(&self) -> Observation {
+        let len = self.observations.len() as u32;
+        self.observations[((self.next_observation + len - 1) % len) as usize]
+    }
+
+    /// Advance the accumulator to `now` at the last trade price. Does nothing
+    /// before the first trade.
+    pub fn advance(&mut self, now: Timestamp) {
+        if self.last_price == 0 || now <= self.last_update {
+            return;
+        }
+        self.price_cumulative = self.price_cumulative_at(now);
+        self.last_update = now;
+        self.observe(now, self.last_trade);
+    }
+
+    /// Record the closing price of a settlement.
+    pub fn record_trade(&mut self, price: LotBalance, now: Timestamp) {
+        if self.last_price == 0 {
+            self.last_update = now;
+            self.observe(now, now);
+        } else {
+            self.advance(now);
+        }
+        self.last_price = price;
+        self.last_trade = now;
+    }
+
+    /// Time-weighted average price in lots over at least `window`
+    /// nanoseconds, and the actual window used. The window is shorter than
+    /// requested only if the oracle doesn't have observations going back that
+    /// far. Returns `None` before the first trade.
+    pub fn twap(&self, now: Timestamp, window: u64) -> Option<(LotBalance, u64)> {
+        if self.last_price == 0 {
+            return None;
+        }
+        let target = now.saturating_sub(window);
+        if target >= self.last_trade {
+            // no trades in the window
+            return Some((self.last_price, now - target));
+        }
+        let len = self.observations.len() as u32;
+        // walk observations newest to oldest until one is old enough
+        let oldest_index = (self.next_observation + len - self.observation_count) % len;
+        let mut base = self.observations[oldest_index as usize];
+        let mut after: Option<Observation> = None;
+        for i in 1..=self.observation_count {
+            let obs = self.observations[((self.next_observation + len - i) % len) as usize];
+            if obs.timestamp <= target {
+                base = obs;
+                break;
+            }
+            after = Some(obs);
+        }
+
+        // If the window starts while the price recorded by the next
+        // observation was in effect, the accumulator is known exactly there.
+        if let Some(after) = after.filter(|obs| obs.price_since <= target) {
+            let target_cumulative = after
+                .price_cumulative
+                .wrapping_sub(after.price as u128 * (after.timestamp - target) as u128);
+            let actual_window = now - target;
+            let price = self
+                .price_cumulative_at(now)
+                .wrapping_sub(target_cumulative)
+                / actual_window as u128;
+            return Some((price as LotBalance, actual_window));
+        }
+
+        let actual_window = now.saturating_sub(base.timestamp);
+        if actual_window == 0 {
+            return Some((self.last_price, 0));
+        }
+        let price = self
+            .price_cumulative_at(now)
+            .wrapping_sub(base.price_cumulative)
+            / actual_window as u128;
+        Some((price as LotBalance, actual_window))
+    }
+}
+
+impl Contract {
+    pub fn internal_get_oracle(&self, market_id: &MarketId) -> PriceOracle {
+        self.oracles.get(market_id).unwrap_or_default()
+    }
+
+    /// Markets created before the oracle existed get theirs on the first
+    /// trade, paid for by the contract.
+    pub fn internal_record_oracle_price(&mut self, market_id: &MarketId, price: LotBalance) {
+        let mut oracle = self.internal_get_oracle(market_id);
+        oracle.record_trade(price, env::block_timestamp());
+        self.oracles.insert(market_id, &oracle);
+    }
+
+    /// Advance the oracle when the top of the book moves without a trade.
+    /// The accumulator is only written when an observation is due; until
+    /// then reads extrapolate it just the same.
+    pub fn internal_advance_oracle(&mut self, market_id: &MarketId) {
+        let now = env::block_timestamp();
+        let mut oracle = match self.oracles.get(market_id) {
+            Some(oracle) if oracle.last_price != 0 && oracle.observation_due(now) => oracle,
+            _ => return,
+        };
+        oracle.advance(now);
+        self.oracles.insert(market_id, &oracle);
+    }
+}
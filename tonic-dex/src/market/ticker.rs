@@ -0,0 +1,171 @@
+/// Implements per-market trade statistics and a cached top of book, so that
+/// tickers can be read without loading the orderbook.
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::Timestamp;
+
+use crate::*;
+
+const NS_PER_HOUR: u64 = 3_600 * 1_000_000_000;
+
+/// Rolling stats cover the last 24 hourly buckets, including the current one,
+/// so the window is between 23 and 24 hours long.
+pub const STATS_WINDOW_HOURS: u64 = 24;
+
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct PriceLevel {
+    pub price: Balance,
+    pub quantity: Balance,
+}
+
+/// Best price and total quantity on each side. Updated whenever the orderbook
+/// is saved.
+#[derive(Clone, Debug, Default, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct TopOfBook {
+    pub bid: Option<PriceLevel>,
+    pub ask: Option<PriceLevel>,
+}
+
+impl TopOfBook {
+    pub fn from_market(market: &Market) -> Self {
+        let level = |price_lots: LotBalance, quantity_lots: LotBalance| PriceLevel {
+            price: market.quote_lots_to_native(price_lots),
+            quantity: market.base_lots_to_native(quantity_lots),
+        };
+        Self {
+            bid: market
+                .orderbook
+                .bids
+                .take_depth(1)
+                .first()
+                .map(|(_, orders)| {
+                    level(
+                        orders[0].unwrap_price(),
+                        orders.iter().map(|o| o.open_qty_lots).sum(),
+                    )
+                }),
+            ask: market
+                .orderbook
+                .asks
+                .take_depth(1)
+                .first()
+                .map(|(_, orders)| {
+                    level(
+                        orders[0].unwrap_price(),
+                        orders.iter().map(|o| o.open_qty_lots).sum(),
+                    )
+                }),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, BorshDeserialize, BorshSerialize)]
+pub struct HourlyBucket {
+    /// Hours since the unix epoch.
+    pub hour: u64,
+    pub base_volume: Balance,
+    pub quote_volume: Balance,
+    pub high: Balance,
+    pub low: Balance,
+}
+
+#[derive(Clone, Debug, BorshDeserialize, BorshSerialize)]
+pub struct MarketStats {
+    pub last_price: Option<Balance>,
+    pub last_quantity: Balance,
+    pub last_trade_timestamp: Timestamp,
+
+    /// Ring buffer of hourly buckets, indexed by hour modulo the window size.
+    /// Allocated up front so that the storage cost is paid at market creation.
+    pub hourly: Vec<HourlyBucket>,
+}
+
+impl Default for MarketStats {
+    fn default() -> Self {
+        Self {
+            last_price: None,
+            last_quantity: 0,
+            last_trade_timestamp: 0,
+            hourly: vec![HourlyBucket::default(); STATS_WINDOW_HOURS as usize],
+        }
+    }
+}
+
+/// Totals over the rolling 24 hour window.
+#[derive(Clone, Debug, Default)]
+pub struct RollingStats {
+    pub base_volume: Balance,
+    pub quote_volume: Balance,
+    pub high: Option<Balance>,
+    pub low: Option<Balance>,
+}
+
+impl MarketStats {
+    pub fn record_trade(
+        &mut self,
+        price: Balance,
+        quantity: Balance,
+        quote_quantity: Balance,
+        timestamp: Timestamp,
+    ) {
+        self.last_price = Some(price);
+        self.last_quantity = quantity;
+        self.last_trade_timestamp = timestamp;
+
+        let hour = timestamp / NS_PER_HOUR;
+        let bucket = &mut self.hourly[(hour % STATS_WINDOW_HOURS) as usize];
+        if bucket.hour != hour || bucket.base_volume == 0 {
+            *bucket = HourlyBucket {
+                hour,
+                base_volume: 0,
+                quote_volume: 0,
+                high: price,
+                low: price,
+            };
+        }
+        bucket.base_volume += quantity;
+        bucket.quote_volume += quote_quantity;
+        bucket.high = bucket.high.max(price);
+        bucket.low = bucket.low.min(price);
+    }
+
+    pub fn rolling(&self, now: Timestamp) -> RollingStats {
+        let hour = now / NS_PER_HOUR;
+        self.hourly
+            .iter()
+            .filter(|b| b.base_volume > 0 && b.hour + STATS_WINDOW_HOURS > hour)
+            .fold(RollingStats::default(), |acc, b| RollingStats {
+                base_volume: acc.base_volume + b.base_volume,
+                quote_volume: acc.quote_volume + b.quote_volume,
+                high: Some(acc.high.map_or(b.high, |h| h.max(b.high))),
+                low: Some(acc.low.map_or(b.low, |l| l.min(b.low))),
+            })
+    }
+}
+
+impl Contract {
+    pub fn internal_get_market_stats(&self, market_id: &MarketId) -> MarketStats {
+        self.market_stats.get(market_id).unwrap_or_default()
+    }
+
+    pub fn internal_save_market_stats(&mut self, market_id: &MarketId, stats: &MarketStats) {
+        self.market_stats.insert(market_id, stats);
+    }
+
+    /// Return the cached top of book. Markets that haven't been saved since the
+    /// cache was added fall back to reading the orderbook.
+    pub fn internal_get_top_of_book(&self, market_id: &MarketId) -> Option<TopOfBook> {
+        self.top_of_book.get(market_id).or_else(|| {
+            self.internal_get_market(market_id)
+                .map(|m| TopOfBook::from_market(&m))
+        })
+    }
+
+    /// Refresh the cached top of book, writing only if it changed.
+    pub fn internal_update_top_of_book(&mut self, market_id: &MarketId, market: &Market) {
+        let top = TopOfBook::from_market(market);
+        if self.top_of_book.get(market_id).as_ref() != Some(&top) {
+            self.top_of_book.insert(market_id, &top);
+            self.internal_advance_oracle(market_id);
+        }
+    }
+}
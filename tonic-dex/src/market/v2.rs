@@ -0,0 +1,335 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+
+use crate::*;
+
+#[derive(
+    Copy, Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+#[repr(u8)]
+pub enum MarketState {
+    /// Market was created but is pending receipt of token decimal info
+    Uninitialized,
+    /// Market allows trading
+    Active,
+    /// Market does not allow any trading operations
+    Paused,
+    /// Market only allows cancelling existing orders
+    CancelOnly,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Token {
+    pub token_type: TokenType,
+    pub lot_size: u128,
+    pub decimals: u8,
+}
+
+/// Let
+/// * `L_q` = quote lot size
+/// * `L_b` = base lot size
+/// * `D_b` = base token decimals
+///
+/// Audit 5.2: Matching in V1 of the orderbook works when the following
+/// condition holds:
+///
+/// ```md
+/// L_q * L_b >= 10^{D_b}
+/// ```
+///
+/// This is validated in the `on_ft_metadata` receiver after market creation.
+#[derive(Debug, BorshDeserialize, BorshSerialize)]
+pub struct Market {
+    /// ID of this market, initialized at runtime after loading from trie for
+    /// convenience. Not directly serialized to trie.
+    #[borsh_skip]
+    pub id: Option<MarketId>,
+
+    pub state: MarketState,
+    pub base_token: Token,
+    pub quote_token: Token,
+
+    /// Stored inline, so loading or saving a market reads and writes the
+    /// whole book. `VecOrderbook` keeps each side in a single vector; keeping
+    /// price levels in their own trie entries would need a trie-backed
+    /// orderbook in tonic-sdk. Views that only need the state and tokens read
+    /// them from `market_headers` instead.
+    pub orderbook: VecOrderbook,
+
+    /// Integer number bps.
+    pub taker_fee_base_rate: u8,
+
+    /// Integer number bps.
+    pub maker_rebate_base_rate: u8,
+
+    pub max_orders_per_account: u8,
+
+    /// Net taker fees (ie, after maker and referrer rebates) accrued to the
+    /// contract, denominated in the quote currency.
+    pub fees_accrued: Balance,
+
+    /// Minimum percent of best bid for a new order price, in bps
+    pub minimum_bid_bps: u32,
+
+    /// Maximum percent of best ask for a new order price, in bps
+    pub maximum_ask_bps: u32,
+
+    /// Account that created the market and paid its storage deposit. Markets
+    /// created before creators were recorded have none.
+    pub creator_id: Option<AccountId>,
+
+    /// Share of net taker fees paid to the market creator, in bps.
+    pub creator_fee_share_bps: u32,
+
+    /// Creator's share of net taker fees, denominated in the quote currency.
+    /// Not included in `fees_accrued`.
+    pub creator_fees_accrued: Balance,
+
+    /// Storage deposit paid by the creator, refunded when the market is
+    /// deleted.
+    pub storage_deposit: Balance,
+
+    /// Display symbol of the base token, eg, "NEAR". Taken from the token
+    /// registry or token metadata.
+    pub base_symbol: Option<String>,
+
+    /// Display symbol of the quote token, eg, "USDC".
+    pub quote_symbol: Option<String>,
+
+    /// Limit prices must be a multiple of this amount, with decimals. Always a
+    /// multiple of the quote lot size.
+    pub tick_size: Balance,
+
+    /// Set by the contract owner for markets listing the tokens their symbols
+    /// claim to be.
+    pub verified: bool,
+
+    /// True if the current state was set by the contract owner. The creator
+    /// can't override it.
+    pub state_set_by_owner: bool,
+}
+
+impl Market {
+    impl_lazy_accessors!(id, unwrap_id, initialize_id, MarketId);
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum PairSide {
+    Base,
+    Quote,
+}
+
+/// Parameters for a new order. Limit price is ignored for market orders.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NewOrderParams {
+    /// Limit price with decimals.
+    pub limit_price: Option<U128>,
+
+    /// Maximum amount to spend with decimals (bids only).
+    pub max_spend: Option<U128>,
+
+    /// Quantity to buy/sell with decimals.
+    pub quantity: U128,
+
+    pub side: Side,
+
+    pub order_type: OrderType,
+
+    /// Optional ID for caller's own accounting.
+    pub client_id: Option<u32>,
+
+    /// Account that receives a portion of taker fees if any part of this order
+    /// fills immediately.
+    pub referrer_id: Option<AccountId>,
+}
+
+pub(crate) fn denomination(decimals: u8) -> u128 {
+    10u128.pow(decimals.into())
+}
+
+impl Market {
+    /// See [Market] struct for discussion on valid lot sizes.
+    ///
+    /// Called during the `on_ft_metadata` callback.
+    pub fn validate_lots_and_decimals(&self) -> bool {
+        self.base_token.decimals != INVALID_DECIMALS
+            && self.quote_token.decimals != INVALID_DECIMALS
+            && U256::from(self.base_token.lot_size) * U256::from(self.quote_token.lot_size)
+                >= U256::from(self.base_denomination())
+    }
+
+    pub fn base_denomination(&self) -> Balance {
+        denomination(self.base_token.decimals)
+    }
+
+    pub fn quote_denomination(&self) -> Balance {
+        denomination(self.quote_token.decimals)
+    }
+
+    pub fn quote_lots_to_native(&self, lots: LotBalance) -> Balance {
+        lots as u128 * self.quote_token.lot_size as u128
+    }
+
+    pub fn quote_native_to_lots(&self, amount: Balance) -> LotBalance {
+        (amount / self.quote_token.lot_size as u128) as u64
+    }
+
+    pub fn base_lots_to_native(&self, lots: LotBalance) -> Balance {
+        lots as u128 * self.base_token.lot_size as u128
+    }
+
+    pub fn base_native_to_lots(&self, amount: Balance) -> LotBalance {
+        (amount / self.base_token.lot_size as u128) as u64
+    }
+
+    /// Balance locked by an order resting at `price_lots` with `open_qty_lots`
+    /// open: quote for bids, base for asks.
+    pub fn locked_for_order(
+        &self,
+        side: Side,
+        price_lots: LotBalance,
+        open_qty_lots: LotBalance,
+    ) -> (Balance, TokenType) {
+        match side {
+            Side::Buy => {
+                let amount = (U256::from(open_qty_lots)
+                    * U256::from(price_lots)
+                    * U256::from(self.quote_token.lot_size)
+                    * U256::from(self.base_token.lot_size)
+                    / U256::from(self.base_denomination()))
+                .as_u128();
+                (amount, self.quote_token.token_type.clone())
+            }
+            Side::Sell => (
+                (open_qty_lots as u128)
+                    .checked_mul(self.base_token.lot_size)
+                    .unwrap(),
+                self.base_token.token_type.clone(),
+            ),
+        }
+    }
+
+    /// Value in the quote token of `quantity` base tokens at `price`, both with
+    /// decimals.
+    pub fn notional(&self, price: Balance, quantity: Balance) -> Balance {
+        (U256::from(quantity) * U256::from(price) / self.base_denomination()).as_u128()
+    }
+
+    pub fn fee_calculator(&self, account: &Account) -> FeeCalculator {
+        FeeCalculator::new(account, self)
+    }
+
+    /// Ticker symbol, eg, "NEAR/USDC". Only available once both token symbols
+    /// are known.
+    pub fn symbol(&self) -> Option<String> {
+        match (&self.base_symbol, &self.quote_symbol) {
+            (Some(base), Some(quote)) => Some(format!("{}/{}", base, quote)),
+            _ => None,
+        }
+    }
+
+    pub fn assert_valid_tick_size(&self, tick_size: Balance) {
+        _assert!(
+            tick_size > 0 && tick_size % self.quote_token.lot_size == 0,
+            "Tick size must be a multiple of the quote lot size"
+        );
+    }
+
+    pub fn assert_valid_price(&self, limit_price: Balance) {
+        _assert!(
+            limit_price % self.tick_size == 0,
+            "Limit price must be a multiple of the tick size"
+        );
+    }
+
+    pub fn token(&self, side: &PairSide) -> &TokenType {
+        match side {
+            PairSide::Base => &self.base_token.token_type,
+            PairSide::Quote => &self.quote_token.token_type,
+        }
+    }
+
+    pub fn set_decimals(&mut self, side: PairSide, decimals: u8) {
+        match side {
+            PairSide::Base => self.base_token.decimals = decimals,
+            PairSide::Quote => self.quote_token.decimals = decimals,
+        }
+    }
+
+    /// Accrue net taker fees, setting aside the creator's share.
+    pub fn incr_fees_accrued(&mut self, amount: Balance) {
+        let creator_fee = if self.creator_id.is_some() {
+            amount * self.creator_fee_share_bps as u128 / FEE_TO_BPS_DIVISOR
+        } else {
+            0
+        };
+        self.creator_fees_accrued += creator_fee;
+        self.fees_accrued += amount - creator_fee;
+    }
+
+    pub fn is_creator(&self, account_id: &AccountId) -> bool {
+        self.creator_id.as_ref() == Some(account_id)
+    }
+
+    pub fn best_bid(&self) -> Option<OpenLimitOrder> {
+        self.orderbook.find_bbo(Side::Buy)
+    }
+
+    pub fn best_ask(&self) -> Option<OpenLimitOrder> {
+        self.orderbook.find_bbo(Side::Sell)
+    }
+
+    pub fn place_order(
+        &mut self,
+        sequence_number: SequenceNumber,
+        owner_id: &AccountId,
+        limit_price_lots: Option<LotBalance>,
+        max_qty_lots: LotBalance,
+        available_quote_lots: Option<LotBalance>,
+        side: Side,
+        order_type: OrderType,
+        client_id: Option<ClientId>,
+    ) -> PlaceOrderResult {
+        self.assert_active();
+
+        self.orderbook.place_order(
+            owner_id,
+            NewOrder {
+                sequence_number,
+                limit_price_lots,
+                max_qty_lots,
+                available_quote_lots,
+                side,
+                order_type,
+                quote_lot_size: self.quote_token.lot_size,
+                base_denomination: self.base_denomination(),
+                base_lot_size: self.base_token.lot_size,
+                client_id,
+            },
+        )
+    }
+
+    pub fn assert_active(&self) {
+        _assert_eq!(
+            self.state,
+            MarketState::Active,
+            "Market must be active to place an order"
+        );
+    }
+
+    pub fn assert_can_cancel(&self) {
+        _assert!(
+            self.state == MarketState::Active || self.state == MarketState::CancelOnly,
+            "Market must be active or cancel-only to cancel an order"
+        );
+    }
+
+    pub fn set_state(&mut self, new_state: MarketState, set_by_owner: bool) {
+        self.state = new_state;
+        self.state_set_by_owner = set_by_owner;
+    }
+}
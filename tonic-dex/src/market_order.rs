@@ -6,7 +6,7 @@ impl Contract {
         &mut self,
         market: &mut Market,
         taker_account_id: AccountId,
-        taker_account: &mut AccountV1,
+        taker_account: &mut Account,
         params: NewOrderParams,
     ) -> PlaceOrderResult {
         let NewOrderParams {
@@ -51,6 +51,7 @@ impl Contract {
             base_traded,
             quote_traded,
             total_maker_rebate,
+            ..
         } = self.internal_settle_maker_fills(
             market,
             result.id,
@@ -103,7 +104,10 @@ impl Contract {
 
         taker_account.withdraw(&input_token, input_debit);
         taker_account.deposit(&output_token, output_credit);
-        market.incr_fees_accrued(total_taker_fee - total_maker_rebate - referrer_rebate);
+        self.internal_incr_fees_accrued(
+            market,
+            total_taker_fee - total_maker_rebate - referrer_rebate,
+        );
 
         emit_event(EventType::Order(NewOrderEvent {
             account_id: taker_account_id.clone(),
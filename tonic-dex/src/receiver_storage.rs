@@ -0,0 +1,335 @@
+/// Implements pre-flight checks that the receiver of a fungible token
+/// withdrawal is registered with the token contract. Unregistered receivers are
+/// registered with `storage_deposit`, paid for by the withdrawing account,
+/// instead of letting the transfer fail and be reverted.
+use near_contract_standards::storage_management::{StorageBalance, StorageBalanceBounds};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{ext_contract, serde_json, Gas, PromiseOrValue, PromiseResult};
+
+use crate::*;
+
+/// 5 TGas
+pub const GAS_FOR_STORAGE_BALANCE_OF: Gas = Gas(5_000_000_000_000);
+/// 10 TGas
+pub const GAS_FOR_STORAGE_DEPOSIT: Gas = Gas(10_000_000_000_000);
+/// 10 TGas, the most either callback uses itself: a failed storage deposit
+/// for an account that no longer exists holds both refunds as unclaimed
+pub const GAS_FOR_RECEIVER_STORAGE_CALLBACK: Gas = Gas(10_000_000_000_000);
+/// 70 TGas, the callback plus a transfer call and its resolution
+pub const GAS_FOR_RECEIVER_STORAGE_DEPOSIT: Gas = Gas(GAS_FOR_RECEIVER_STORAGE_CALLBACK.0
+    + GAS_FOR_FT_TRANSFER_CALL.0
+    + GAS_FOR_RESOLVE_TRANSFER.0);
+/// 90 TGas, the callback plus a storage deposit and its callback
+pub const GAS_FOR_RECEIVER_STORAGE_CHECKED: Gas = Gas(GAS_FOR_RECEIVER_STORAGE_CALLBACK.0
+    + GAS_FOR_STORAGE_DEPOSIT.0
+    + GAS_FOR_RECEIVER_STORAGE_DEPOSIT.0);
+
+/// Where the NEAR for a receiver's storage deposit comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub enum StoragePayment {
+    /// The withdrawing account's NEAR exchange balance.
+    ExchangeBalance,
+    /// The withdrawing account's available storage balance.
+    StorageBalance,
+}
+
+#[ext_contract(ext_ft_storage)]
+pub trait FungibleTokenStorage {
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance>;
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds;
+
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance;
+}
+
+#[ext_contract(ext_receiver_storage)]
+pub trait ReceiverStorageCallback {
+    fn on_receiver_storage_checked(
+        &mut self,
+        account_id: AccountId,
+        receiver_id: AccountId,
+        token_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: Option<String>,
+        payment: StoragePayment,
+    ) -> PromiseOrValue<U128>;
+
+    fn on_receiver_storage_deposit(
+        &mut self,
+        account_id: AccountId,
+        receiver_id: AccountId,
+        token_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: Option<String>,
+        payment: StoragePayment,
+        deposit: U128,
+    ) -> PromiseOrValue<U128>;
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Send a withdrawal whose amount has already been debited, after checking
+    /// the receiver's registration with the token. Emits a
+    /// `withdraw_receiver_storage` event with the path taken:
+    /// * `registered`: the receiver was registered
+    /// * `unchecked`: the token didn't report a storage balance
+    /// * `storage_deposit`: the receiver is being registered first, see
+    ///   [Contract::on_receiver_storage_deposit]
+    /// * `refunded`: the account couldn't pay for the deposit, and the
+    ///   withdrawal was credited back. Returns the amount credited.
+    ///
+    /// Never panics: amounts that can't be credited back are held as
+    /// unclaimed.
+    #[private]
+    #[allow(clippy::too_many_arguments)]
+    pub fn on_receiver_storage_checked(
+        &mut self,
+        account_id: AccountId,
+        receiver_id: AccountId,
+        token_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: Option<String>,
+        payment: StoragePayment,
+    ) -> PromiseOrValue<U128> {
+        _assert_eq!(
+            env::promise_results_count(),
+            2,
+            "Expected two promise results"
+        );
+        let registered = match env::promise_result(0) {
+            PromiseResult::Successful(value) => {
+                serde_json::from_slice::<Option<StorageBalance>>(&value)
+                    .ok()
+                    .map(|balance| balance.is_some())
+            }
+            _ => None,
+        };
+        let min_deposit = match env::promise_result(1) {
+            PromiseResult::Successful(value) => {
+                serde_json::from_slice::<StorageBalanceBounds>(&value)
+                    .ok()
+                    .map(|bounds| bounds.min.0)
+            }
+            _ => None,
+        };
+
+        let token = TokenType::from_account_id(token_id.clone());
+        let (path, deposit) = match (registered, min_deposit) {
+            (Some(true), _) => (ReceiverStoragePath::Registered, 0),
+            (Some(false), Some(min_deposit)) => {
+                if self.internal_pay_receiver_storage(&account_id, payment, min_deposit) {
+                    (ReceiverStoragePath::StorageDeposit, min_deposit)
+                } else {
+                    (ReceiverStoragePath::Refunded, 0)
+                }
+            }
+            _ => (ReceiverStoragePath::Unchecked, 0),
+        };
+        emit_dex_event(DexEventType::WithdrawReceiverStorage(
+            WithdrawReceiverStorageEvent {
+                account_id: account_id.clone(),
+                receiver_id: receiver_id.clone(),
+                token: token.key(),
+                path,
+            },
+        ));
+
+        match path {
+            ReceiverStoragePath::Refunded => {
+                self.internal_credit_or_escrow(&account_id, &token, amount.0);
+                PromiseOrValue::Value(amount)
+            }
+            ReceiverStoragePath::StorageDeposit => PromiseOrValue::Promise(
+                ext_ft_storage::storage_deposit(
+                    Some(receiver_id.clone()),
+                    Some(true),
+                    token_id.clone(),
+                    deposit,
+                    GAS_FOR_STORAGE_DEPOSIT,
+                )
+                .then(ext_receiver_storage::on_receiver_storage_deposit(
+                    account_id,
+                    receiver_id,
+                    token_id,
+                    amount,
+                    memo,
+                    msg,
+                    payment,
+                    U128(deposit),
+                    env::current_account_id(),
+                    0,
+                    GAS_FOR_RECEIVER_STORAGE_DEPOSIT,
+                )),
+            ),
+            _ => PromiseOrValue::Promise(self.internal_send_to(
+                &account_id,
+                &receiver_id,
+                &token,
+                amount.0,
+                memo,
+                msg,
+            )),
+        }
+    }
+
+    /// Send a withdrawal once the receiver's storage deposit has gone through.
+    /// If the deposit failed, the token contract returned the NEAR attached to
+    /// it: the payment and the withdrawal are credited back, a
+    /// `withdraw_receiver_storage` event with the `storage_deposit_failed` path
+    /// is emitted, and the amount credited is returned.
+    #[private]
+    #[allow(clippy::too_many_arguments)]
+    pub fn on_receiver_storage_deposit(
+        &mut self,
+        account_id: AccountId,
+        receiver_id: AccountId,
+        token_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: Option<String>,
+        payment: StoragePayment,
+        deposit: U128,
+    ) -> PromiseOrValue<U128> {
+        _assert_eq!(
+            env::promise_results_count(),
+            1,
+            "Expected one promise result"
+        );
+        let token = TokenType::from_account_id(token_id);
+        if let PromiseResult::Successful(_) = env::promise_result(0) {
+            return PromiseOrValue::Promise(self.internal_send_to(
+                &account_id,
+                &receiver_id,
+                &token,
+                amount.0,
+                memo,
+                msg,
+            ));
+        }
+
+        self.internal_refund_receiver_storage(&account_id, payment, deposit.0);
+        self.internal_credit_or_escrow(&account_id, &token, amount.0);
+        emit_dex_event(DexEventType::WithdrawReceiverStorage(
+            WithdrawReceiverStorageEvent {
+                account_id,
+                receiver_id,
+                token: token.key(),
+                path: ReceiverStoragePath::StorageDepositFailed,
+            },
+        ));
+        PromiseOrValue::Value(amount)
+    }
+}
+
+impl Contract {
+    /// Debit `account_id` and send to `receiver_id` once the receiver is known
+    /// to be registered with the token.
+    #[allow(clippy::too_many_arguments)]
+    pub fn internal_withdraw_ft_checked(
+        &mut self,
+        account_id: &AccountId,
+        receiver_id: &AccountId,
+        token_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+        msg: Option<String>,
+        payment: StoragePayment,
+    ) -> Promise {
+        _assert!(amount > 0, "Withdraw amount must be positive");
+
+        let mut account = self.internal_unwrap_account(account_id);
+        account.withdraw(&TokenType::from_account_id(token_id.clone()), amount);
+        self.internal_save_account(account_id, account);
+
+        ext_ft_storage::storage_balance_of(
+            receiver_id.clone(),
+            token_id.clone(),
+            0,
+            GAS_FOR_STORAGE_BALANCE_OF,
+        )
+        .and(ext_ft_storage::storage_balance_bounds(
+            token_id.clone(),
+            0,
+            GAS_FOR_STORAGE_BALANCE_OF,
+        ))
+        .then(ext_receiver_storage::on_receiver_storage_checked(
+            account_id.clone(),
+            receiver_id.clone(),
+            token_id.clone(),
+            U128(amount),
+            memo,
+            msg,
+            payment,
+            env::current_account_id(),
+            0,
+            GAS_FOR_RECEIVER_STORAGE_CHECKED,
+        ))
+    }
+
+    /// Debit the NEAR for a receiver's storage deposit. Returns false without
+    /// changing anything if the account can't cover it.
+    fn internal_pay_receiver_storage(
+        &mut self,
+        account_id: &AccountId,
+        payment: StoragePayment,
+        amount: Balance,
+    ) -> bool {
+        let mut account = match self.internal_get_account(account_id) {
+            Some(account) => account,
+            None => return false,
+        };
+        match payment {
+            StoragePayment::ExchangeBalance => {
+                if account.get_balance(&TokenType::NativeNear) < amount {
+                    return false;
+                }
+                account.withdraw(&TokenType::NativeNear, amount);
+            }
+            StoragePayment::StorageBalance => {
+                if account.storage_balance_available() < amount {
+                    return false;
+                }
+                account.storage_balance -= amount;
+            }
+        }
+        if self.internal_try_save_account(account_id, account).is_err() {
+            return false;
+        }
+        if payment == StoragePayment::ExchangeBalance {
+            self.internal_record_withdrawal(&TokenType::NativeNear, amount);
+        }
+        true
+    }
+
+    /// Credit back the NEAR paid for a receiver storage deposit that failed.
+    /// Held as unclaimed if the account no longer exists.
+    fn internal_refund_receiver_storage(
+        &mut self,
+        account_id: &AccountId,
+        payment: StoragePayment,
+        amount: Balance,
+    ) {
+        match payment {
+            StoragePayment::ExchangeBalance => {
+                self.internal_revert_withdrawal(&TokenType::NativeNear, amount);
+                self.internal_credit_or_escrow(account_id, &TokenType::NativeNear, amount);
+            }
+            StoragePayment::StorageBalance => match self.internal_get_account(account_id) {
+                Some(mut account) => {
+                    account.storage_balance += amount;
+                    self.internal_save_maker_account(account_id, account);
+                }
+                None => self.internal_escrow_unclaimed(account_id, &TokenType::NativeNear, amount),
+            },
+        }
+    }
+}
@@ -10,6 +10,8 @@ pub struct MakerSettlementResult {
     /// Total amount of quote traded
     pub quote_traded: Balance,
     pub total_maker_rebate: Balance,
+    /// Orderbook storage released by maker orders removed from the book
+    pub orderbook_storage_released: StorageUsage,
 }
 
 impl Contract {
@@ -32,21 +34,59 @@ impl Contract {
         let quote_lot_size = market.quote_token.lot_size as u128;
 
         let mut total_maker_rebate: Balance = 0;
+        let mut orderbook_storage_released: StorageUsage = 0;
         let mut base_traded: Balance = 0; // amount of base purchased in bid, amount sold in ask
         let mut quote_traded: Balance = 0; // amount of quote spent in bid, amount received in ask
 
         let mut fills: Vec<FillEventData> = vec![];
+        let mut stats = self.internal_get_market_stats(&market.unwrap_id());
+        let mut candle_fills: Vec<(LotBalance, LotBalance)> = vec![];
 
         for fill in matches.iter() {
             let native_fill_price = (fill.fill_price_lots as u128) * quote_lot_size;
             let native_fill_qty = (fill.fill_qty_lots as u128) * base_lot_size;
             base_traded += native_fill_qty;
             quote_traded += fill.native_quote_paid;
+            if fill.fill_qty_lots > 0 {
+                stats.record_trade(
+                    native_fill_price,
+                    native_fill_qty,
+                    fill.native_quote_paid,
+                    env::block_timestamp(),
+                );
+                candle_fills.push((fill.fill_price_lots, fill.fill_qty_lots));
+            }
 
             let mut maker_account = self.internal_unwrap_account(&fill.maker_user_id);
-            if fill.did_remove_maker_order() {
+            let maker_open_qty_lots = if fill.did_remove_maker_order() {
+                let orderbook_storage = maker_account.get_orderbook_storage();
                 maker_account.remove_order_info(&market.unwrap_id(), fill.maker_order_id);
-            }
+                orderbook_storage_released +=
+                    orderbook_storage - maker_account.get_orderbook_storage();
+                0
+            } else {
+                market
+                    .orderbook
+                    .get_order(fill.maker_order_id)
+                    .map(|o| o.open_qty_lots)
+                    .unwrap_or_default()
+            };
+
+            // Release what the filled part of the maker order had locked. The
+            // difference is taken so that rounding matches the refund on
+            // cancel.
+            let maker_side = match side {
+                Side::Buy => Side::Sell,
+                Side::Sell => Side::Buy,
+            };
+            let (locked_before, locked_token) = market.locked_for_order(
+                maker_side,
+                fill.fill_price_lots,
+                maker_open_qty_lots + fill.fill_qty_lots,
+            );
+            let (locked_after, _) =
+                market.locked_for_order(maker_side, fill.fill_price_lots, maker_open_qty_lots);
+            maker_account.unlock(&locked_token, locked_before - locked_after);
 
             let fee_calculator = FeeCalculator::new(&maker_account, market);
             let native_maker_rebate = fee_calculator.maker_rebate(fill.native_quote_paid);
@@ -64,7 +104,7 @@ impl Contract {
                 }
             }
 
-            self.internal_save_account(&fill.maker_user_id, maker_account);
+            self.internal_save_maker_account(&fill.maker_user_id, maker_account);
 
             fills.push(FillEventData {
                 fill_qty: native_fill_qty.into(),
@@ -79,6 +119,13 @@ impl Contract {
             });
         }
 
+        // market data only changes if something traded
+        if let Some((closing_price, _)) = candle_fills.last() {
+            self.internal_save_market_stats(&market.unwrap_id(), &stats);
+            self.internal_record_candles(&market.unwrap_id(), &candle_fills);
+            self.internal_record_oracle_price(&market.unwrap_id(), *closing_price);
+        }
+
         emit_event(EventType::Fill(NewFillEvent {
             fills,
             market_id: market.unwrap_id(),
@@ -89,6 +136,7 @@ impl Contract {
             base_traded,
             quote_traded,
             total_maker_rebate,
+            orderbook_storage_released,
         }
     }
 
@@ -10,37 +10,77 @@ pub enum StorageKey {
     Accounts,
     Markets,
     MarketIterMap,
+    StateVersion,
+    Tokens,
+    TokenIterMap,
+    MarketsBySymbol,
+    MarketsByPair,
+    MarketsByToken,
+    MarketStats,
+    TopOfBook,
+    Candles,
+    Oracles,
+    Liabilities,
+    Unclaimed,
+    StoragePrepayments,
+    MarketHeaders,
 }
 
-/// Measured sizes of structs and helper functions for calculating required
-/// storage balance.
-///
-/// Measured with `just measure-storage-usage`.
+/// Sizes of serialized fields and helper functions for calculating required
+/// storage balance. Record sizes are computed from their contents by the
+/// `BorshSize` impls, so they don't need to be re-measured when a struct
+/// changes.
 pub mod size {
     use near_sdk::StorageUsage;
 
-    /// The maximum possible size of an account, ie, one with a 64-byte ID.
-    /// Measured using `just test-storage`.
-    pub const ACCOUNT: StorageUsage = 134;
+    /// Bytes charged for each trie record on top of its key and value. This is
+    /// `storage_num_extra_bytes_record` in the runtime config.
+    pub const DATA_RECORD_OVERHEAD: StorageUsage = 40;
 
-    /// The size of an order owned by an account with a 64-byte ID, measured
-    /// with just test-storage. This value includes the size of a new price
-    /// level. We can technically measure more precisely than this, but the
-    /// complexity isn't worth it.
-    pub const OPEN_LIMIT_ORDER: StorageUsage = 93;
+    /// Size of the [StorageKey](super::StorageKey) prefix of a collection key.
+    pub const STORAGE_KEY_PREFIX: StorageUsage = 1;
+
+    /// Borsh length prefix of a string or map.
+    pub const LENGTH_PREFIX: StorageUsage = 4;
+
+    /// Borsh variant tag of a versioned record.
+    pub const ENUM_TAG: StorageUsage = 1;
+
+    pub const BOOL: StorageUsage = 1;
+
+    pub const BALANCE: StorageUsage = 16;
+
+    pub const STORAGE_USAGE: StorageUsage = 8;
 
     /// u128 is 16 bytes. This is serialized as-is by Borsh
     pub const ORDER_ID: StorageUsage = 16;
 
     pub const LOT_BALANCE: StorageUsage = 8;
 
+    pub const TIMESTAMP: StorageUsage = 8;
+
     /// The size of a market ID.
     pub const MARKET_ID: StorageUsage = 32;
 
+    /// The length of the longest possible account ID.
+    pub const MAX_ACCOUNT_ID_LEN: StorageUsage = 64;
+
     /// Fixed amount of storage to lock per market in which an account has open
     /// orders. Ensures that the account has enough storage balance to hold both
-    /// base and quote token balances.
+    /// base and quote token balances when its orders are filled.
     ///
-    /// Size represents account id + balance + account id + balance
-    pub const MARKET_PAIR_OVERHEAD: StorageUsage = 64 + 16 + 64 + 16;
+    /// Size represents two token balance entries with keys as long as an
+    /// account ID.
+    pub const MARKET_PAIR_OVERHEAD: StorageUsage =
+        2 * (LENGTH_PREFIX + MAX_ACCOUNT_ID_LEN + BALANCE);
+
+    /// Orderbook storage charged for each order placed before orderbook
+    /// storage was measured. This was the measured size of an order owned by
+    /// an account with a 64-byte ID, including a new price level.
+    pub const LEGACY_OPEN_LIMIT_ORDER: StorageUsage = 93;
+
+    /// Size of a Borsh-serialized string.
+    pub fn string(s: &str) -> StorageUsage {
+        LENGTH_PREFIX + s.len() as StorageUsage
+    }
 }
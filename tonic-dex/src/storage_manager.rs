@@ -5,7 +5,6 @@ use near_contract_standards::storage_management::{
     StorageBalance, StorageBalanceBounds, StorageManagement,
 };
 use near_sdk::env;
-use tonic_sdk::borsh_size::BorshSize;
 
 use crate::*;
 
@@ -18,9 +17,122 @@ impl Contract {
         }
     }
 
-    pub fn internal_unregister_account(&mut self, account_id: &AccountId, _force: bool) {
-        // let mut account = self.internal_unwrap_account(account_id);
-        self.accounts.remove(account_id);
+    /// Remove an account and refund its storage balance. Returns true if the
+    /// account was removed. Panics if the account isn't empty and `force`
+    /// isn't set.
+    ///
+    /// With `force`, the account's orders are cancelled in every market and
+    /// its balances are sent to it. The account is kept until every transfer
+    /// has succeeded, so failed transfers are credited back to it, and
+    /// removed by [Contract::exchange_callback_post_withdraw_many].
+    pub fn internal_unregister_account(&mut self, account_id: &AccountId, force: bool) -> bool {
+        if force {
+            let market_ids = self
+                .internal_unwrap_account(account_id)
+                .open_order_market_ids();
+            for market_id in market_ids {
+                if self.markets.contains_key(&market_id) {
+                    self.internal_cancel_all_orders(&market_id, account_id.clone());
+                }
+            }
+        }
+
+        let mut account = self.internal_unwrap_account(account_id);
+        _assert!(force || account.is_empty(), "account not empty");
+        let sends: Vec<(TokenType, Balance)> = account
+            .get_balances()
+            .into_iter()
+            .map(|(token_key, amount)| (TokenType::from_key(&token_key), amount))
+            .collect();
+        if sends.is_empty() {
+            self.accounts.remove(account_id);
+            self.internal_refund_storage_balance(account_id, &account);
+            return true;
+        }
+        for (token, amount) in sends.iter() {
+            account.withdraw(token, *amount);
+        }
+        // keep storage from flowing back into the emptied trading balance
+        account.auto_storage = false;
+        account.auto_storage_balance = 0;
+        self.internal_save_maker_account(account_id, account);
+        self.internal_send_many(account_id, sends, true);
+        false
+    }
+
+    /// Unregister an empty account and refund its storage balance. Returns
+    /// false if the account doesn't exist or isn't empty.
+    pub fn internal_try_unregister_account(&mut self, account_id: &AccountId) -> bool {
+        match self.internal_get_account(account_id) {
+            Some(account) if account.is_empty() => {
+                self.accounts.remove(account_id);
+                self.internal_refund_storage_balance(account_id, &account);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Refund the storage balance of a removed account. The part paid from
+    /// the registration pool goes back to the pool.
+    fn internal_refund_storage_balance(&mut self, account_id: &AccountId, account: &Account) {
+        let sponsored = std::cmp::min(account.sponsored_storage, account.storage_balance);
+        self.registration_pool += sponsored;
+        let refund = account.storage_balance - sponsored;
+        if refund > 0 {
+            Promise::new(account_id.clone()).transfer(refund);
+        }
+    }
+
+    /// Remove and return the NEAR prepaid for an account's registration.
+    pub fn internal_take_storage_prepayment(&mut self, account_id: &AccountId) -> Balance {
+        self.storage_prepayments
+            .remove(account_id)
+            .unwrap_or_default()
+    }
+
+    /// Storage balance needed by a new account holding one token.
+    pub fn internal_registration_cost(&self, account_id: &AccountId, token: &TokenType) -> Balance {
+        let mut account = Account::new(account_id);
+        account.deposit(token, 1);
+        std::cmp::max(
+            account.storage_balance_locked(),
+            self.storage_balance_bounds().min.0,
+        )
+    }
+
+    /// Register an account on its first token deposit. Storage is paid from
+    /// the account's prepayment, and any shortfall from the registration pool.
+    /// Panics if the pool can't cover the shortfall, or if it would and the
+    /// deposit is smaller than the token's minimum sponsored deposit.
+    pub fn internal_auto_register(
+        &mut self,
+        account_id: &AccountId,
+        token: &TokenType,
+        amount: Balance,
+    ) {
+        let cost = self.internal_registration_cost(account_id, token);
+        let prepaid = self.internal_take_storage_prepayment(account_id);
+        let sponsored = cost.saturating_sub(prepaid);
+        if sponsored > 0 {
+            let min_deposit = self
+                .internal_get_token_info(token)
+                .and_then(|info| info.min_sponsored_deposit);
+            _assert!(
+                min_deposit.map_or(false, |min| amount >= min),
+                "Deposit is too small to register from the registration pool"
+            );
+        }
+        _assert!(
+            self.registration_pool >= sponsored,
+            errors::INSUFFICIENT_STORAGE_BALANCE
+        );
+        self.registration_pool -= sponsored;
+
+        let mut account = Account::new(account_id);
+        account.storage_balance = prepaid + sponsored;
+        account.sponsored_storage = sponsored;
+        self.internal_save_account(account_id, account);
     }
 
     /// Do storage deposit. Create account if it doesn't exist.
@@ -41,17 +153,22 @@ impl Contract {
                 self.internal_save_account(account_id, account);
             }
         } else {
-            // Making a new account
-            let mut account = AccountV1::new(account_id);
+            // Making a new account, paid from its prepayment first
+            let mut account = Account::new(account_id);
+            let prepaid = self.internal_take_storage_prepayment(account_id);
 
-            let min_balance = Balance::from(account.borsh_size()) * env::storage_byte_cost();
-            _assert!(amount >= min_balance, errors::INSUFFICIENT_STORAGE_BALANCE);
+            let min_balance = account.storage_balance_locked();
+            _assert!(
+                amount + prepaid >= min_balance,
+                errors::INSUFFICIENT_STORAGE_BALANCE
+            );
 
             if registration_only {
-                refund = amount - min_balance;
-                account.storage_balance = min_balance;
+                let used = min_balance.saturating_sub(prepaid);
+                refund = amount - used;
+                account.storage_balance = prepaid + used;
             } else {
-                account.storage_balance = amount;
+                account.storage_balance = prepaid + amount;
             }
 
             self.internal_save_account(account_id, account);
@@ -64,13 +181,12 @@ impl Contract {
         self.internal_get_account(account_id)
             .map(|account| StorageBalance {
                 total: account.storage_balance.into(),
-                available: U128(
-                    account.storage_balance
-                        - std::cmp::max(
-                            Balance::from(account.borsh_size()) * env::storage_byte_cost(),
-                            self.storage_balance_bounds().min.0,
-                        ),
-                ),
+                available: U128(std::cmp::min(
+                    account.storage_balance_available(),
+                    account
+                        .storage_balance
+                        .saturating_sub(self.storage_balance_bounds().min.0),
+                )),
             })
     }
 }
@@ -120,28 +236,32 @@ impl StorageManagement for Contract {
         }
     }
 
-    /// Unregister the account. Panics if the account still has open orders or
-    /// exchange balances. Clients should implement UI for withdrawing all
-    /// balances.
+    /// Unregister the account and refund its storage balance. Returns false
+    /// if the account isn't registered or wasn't unregistered yet.
+    ///
+    /// Panics if the account still has open orders or exchange balances,
+    /// unless `force` is true. With `force`, all of the account's orders are
+    /// cancelled and all of its balances are withdrawn to it. If there were
+    /// balances to withdraw, the account is unregistered once every transfer
+    /// has succeeded.
     #[payable]
-    fn storage_unregister(&mut self, _force: Option<bool>) -> bool {
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
         self.assert_active();
 
         assert_one_yocto();
         let account_id = env::predecessor_account_id();
-        let account = self.internal_unwrap_account(&account_id);
-        if !account.is_empty() {
-            env::panic_str("account not empty");
-        } else {
-            self.accounts.remove(&account_id);
-            Promise::new(account_id.clone()).transfer(account.storage_balance);
-            true
+        if self.internal_get_account(&account_id).is_none() {
+            return false;
         }
+        self.internal_unregister_account(&account_id, force.unwrap_or(false))
     }
 
     fn storage_balance_bounds(&self) -> StorageBalanceBounds {
         StorageBalanceBounds {
-            min: U128(size::ACCOUNT as u128 * near_sdk::env::storage_byte_cost()),
+            min: U128(
+                Balance::from(Account::max_empty_storage_usage())
+                    * near_sdk::env::storage_byte_cost(),
+            ),
             max: None,
         }
     }
@@ -150,3 +270,94 @@ impl StorageManagement for Contract {
         self.internal_storage_balance_of(&account_id)
     }
 }
+
+#[near_bindgen]
+impl Contract {
+    /// Opt in or out of covering storage from the NEAR trading balance. When
+    /// enabled, storage shortfalls are moved from the trading balance into the
+    /// storage balance as orders are placed, and flow back as orders close.
+    /// Storage moved in stays in the storage balance after opting out.
+    /// - Requires one yoctoNEAR.
+    #[payable]
+    pub fn set_auto_storage(&mut self, enabled: bool) {
+        self.assert_active();
+        assert_one_yocto();
+
+        let account_id = env::predecessor_account_id();
+        let mut account = self.internal_unwrap_account(&account_id);
+        account.auto_storage = enabled;
+        if !enabled {
+            account.auto_storage_balance = 0;
+        }
+        self.internal_save_account(&account_id, account);
+    }
+
+    /// Attach NEAR to pay for registering an account on its first token
+    /// deposit with the `Register` action. Defaults to the caller. The first
+    /// prepayment must at least cover the storage of its own record.
+    #[payable]
+    pub fn prepay_storage(&mut self, account_id: Option<AccountId>) {
+        self.assert_active();
+        let account_id = account_id.unwrap_or_else(env::predecessor_account_id);
+        _assert!(
+            self.internal_get_account(&account_id).is_none(),
+            "Account is already registered"
+        );
+        let prepaid = self
+            .storage_prepayments
+            .get(&account_id)
+            .unwrap_or_default();
+        let storage_increase = measure_storage_increase!({
+            self.storage_prepayments
+                .insert(&account_id, &(prepaid + env::attached_deposit()));
+        });
+        _assert!(
+            env::attached_deposit() >= Balance::from(storage_increase) * env::storage_byte_cost(),
+            "Attached deposit must cover the storage of the prepayment"
+        );
+    }
+
+    /// Add the attached NEAR to the pool that pays for registering accounts
+    /// on their first token deposit.
+    #[payable]
+    pub fn fund_registration_pool(&mut self) {
+        self.registration_pool += env::attached_deposit();
+    }
+
+    /// Withdraw from the registration pool. Only callable by the contract
+    /// owner.
+    pub fn withdraw_registration_pool(&mut self, amount: U128) -> Promise {
+        self.assert_is_owner();
+        _assert!(
+            amount.0 <= self.registration_pool,
+            "Amount exceeds registration pool"
+        );
+        self.registration_pool -= amount.0;
+        Promise::new(self.owner_id.clone()).transfer(amount.0)
+    }
+
+    pub fn get_registration_pool(&self) -> U128 {
+        self.registration_pool.into()
+    }
+
+    /// Withdraw the caller's storage prepayment, if they didn't register.
+    /// Returns the amount withdrawn.
+    /// - Requires one yoctoNEAR.
+    #[payable]
+    pub fn withdraw_storage_prepayment(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let amount = self.internal_take_storage_prepayment(&account_id);
+        if amount > 0 {
+            Promise::new(account_id).transfer(amount);
+        }
+        amount.into()
+    }
+
+    pub fn get_storage_prepayment(&self, account_id: AccountId) -> U128 {
+        self.storage_prepayments
+            .get(&account_id)
+            .unwrap_or_default()
+            .into()
+    }
+}
@@ -19,19 +19,27 @@ impl Contract {
 
         let sender_id = env::predecessor_account_id();
         let amount = env::attached_deposit();
-        self.execute_swaps(swaps, TokenType::NativeNear, amount, sender_id)
+        self.internal_record_deposit(&TokenType::NativeNear, amount);
+        let refund = self.execute_swaps(swaps, TokenType::NativeNear, amount, sender_id.clone());
+        if refund > 0 {
+            Promise::new(sender_id).transfer(refund);
+        }
+        PromiseOrValue::Value(U128(refund))
     }
 }
 
 impl Contract {
-    // Unwraps swap parameters and handles ft_transfers
+    /// Unwraps swap parameters and handles ft_transfers. The input must have
+    /// been recorded as deposited. Returns the part of it that the first swap
+    /// didn't use, which the caller refunds; unused input of later swaps is
+    /// sent back to the sender.
     pub fn execute_swaps(
         &mut self,
         swaps: Vec<SwapAction>,
         input_token: TokenType,
         input_amount: Balance,
         sender_id: AccountId,
-    ) -> PromiseOrValue<U128> {
+    ) -> Balance {
         _assert!(!swaps.is_empty(), "At least 1 swap action must be provided");
 
         if swaps.last().unwrap().min_output_token.is_none() {
@@ -39,21 +47,30 @@ impl Contract {
         }
 
         let mut amount = input_amount;
-        let mut token = input_token;
-        for swap in swaps {
-            let result = self.execute_swap_action(swap, token, amount);
+        let mut token = input_token.clone();
+        let mut refund = 0;
+        for (i, swap) in swaps.into_iter().enumerate() {
+            let result = self.execute_swap_action(swap, token.clone(), amount);
             let SwapResult {
-                input_refund: _,
+                input_refund,
                 output_token,
                 output_amount,
             } = result;
+            if i == 0 {
+                refund = input_refund;
+            } else if input_refund > 0 {
+                self.internal_send(&sender_id, &token, input_refund);
+            }
             token = output_token;
             amount = output_amount;
         }
         if amount > 0 {
             self.internal_send(&sender_id, &token, amount);
         }
-        PromiseOrValue::Value(U128(0))
+        if refund > 0 {
+            self.internal_revert_deposit(&input_token, refund);
+        }
+        refund
     }
 
     pub fn execute_swap_action(
@@ -68,6 +85,7 @@ impl Contract {
             min_output_token,
             referrer_id,
         } = swap;
+        self.assert_markets_migrated();
         let mut market = self.internal_unwrap_market(&market_id);
 
         if side == Side::Buy {
@@ -122,6 +140,7 @@ impl Contract {
             total_maker_rebate,
             base_traded,
             quote_traded,
+            ..
         } = self.internal_settle_maker_fills(
             market,
             result.id,
@@ -140,7 +159,7 @@ impl Contract {
             "bid accounting bug: over-counted fees"
         );
         let net_fees_accrued = total_taker_fee - total_maker_rebate - referrer_rebate;
-        market.incr_fees_accrued(net_fees_accrued);
+        self.internal_incr_fees_accrued(market, net_fees_accrued);
 
         let output_amount = match side {
             Side::Buy => base_traded,
@@ -0,0 +1,165 @@
+/// Implements the owner-managed registry of tokens that can be listed in
+/// markets and deposited to the exchange.
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+
+use crate::*;
+
+pub const NEAR_DECIMALS: u8 = 24;
+
+#[derive(
+    Copy, Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize, Serialize, Deserialize,
+)]
+#[serde(crate = "near_sdk::serde")]
+pub enum TokenStatus {
+    /// Token can be listed in new markets and deposited
+    Allowed,
+    /// Token can't be listed or deposited. Existing balances can still be
+    /// traded out of and withdrawn.
+    Blocked,
+}
+
+#[derive(Clone, Debug, PartialEq, BorshDeserialize, BorshSerialize)]
+pub struct TokenInfo {
+    pub status: TokenStatus,
+
+    /// Token decimals. Either set by the owner when registering the token or
+    /// cached from token metadata the first time a market lists the token.
+    pub decimals: Option<u8>,
+
+    /// Token symbol, set and cached the same way as decimals.
+    pub symbol: Option<String>,
+
+    /// Maximum amount accepted in a single deposit. No cap if unset.
+    pub deposit_cap: Option<Balance>,
+
+    /// Smallest deposit that can register its sender from the registration
+    /// pool. Deposits of tokens without a minimum are never sponsored.
+    pub min_sponsored_deposit: Option<Balance>,
+}
+
+impl TokenInfo {
+    pub fn new(decimals: Option<u8>, symbol: Option<String>, deposit_cap: Option<Balance>) -> Self {
+        Self {
+            status: TokenStatus::Allowed,
+            decimals,
+            symbol,
+            deposit_cap,
+            min_sponsored_deposit: None,
+        }
+    }
+
+    /// Native NEAR doesn't need to be registered. Unless the owner registers it
+    /// explicitly, it's treated as allowed with no deposit cap.
+    fn native_near() -> Self {
+        Self::new(Some(NEAR_DECIMALS), Some("NEAR".to_string()), None)
+    }
+
+    pub fn to_view(&self, token_type: TokenType) -> TokenInfoView {
+        TokenInfoView {
+            token_type,
+            status: self.status,
+            decimals: self.decimals,
+            symbol: self.symbol.clone(),
+            deposit_cap: self.deposit_cap.map(U128),
+            min_sponsored_deposit: self.min_sponsored_deposit.map(U128),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenInfoView {
+    pub token_type: TokenType,
+    pub status: TokenStatus,
+    pub decimals: Option<u8>,
+    pub symbol: Option<String>,
+    pub deposit_cap: Option<U128>,
+    pub min_sponsored_deposit: Option<U128>,
+}
+
+impl Contract {
+    pub fn internal_get_token_info(&self, token: &TokenType) -> Option<TokenInfo> {
+        let info = self.tokens.get(&token.key());
+        match token {
+            TokenType::NativeNear => info.or_else(|| Some(TokenInfo::native_near())),
+            _ => info,
+        }
+    }
+
+    /// Add or replace a registry entry.
+    pub fn internal_register_token(&mut self, token: &TokenType, info: TokenInfo) {
+        let key = token.key();
+        if self.tokens.insert(&key, &info).is_none() {
+            self.token_iter_map.push(&key);
+        }
+    }
+
+    /// Register the tokens of a market listed before the registry existed, as
+    /// allowed with no deposit cap. Existing entries are kept.
+    pub fn internal_register_market_tokens(&mut self, market: &Market) {
+        let tokens = [
+            (&market.base_token, &market.base_symbol),
+            (&market.quote_token, &market.quote_symbol),
+        ];
+        for (token, symbol) in tokens {
+            if self.internal_get_token_info(&token.token_type).is_some() {
+                continue;
+            }
+            let decimals = match market.state {
+                MarketState::Uninitialized => None,
+                _ => Some(token.decimals),
+            };
+            self.internal_register_token(
+                &token.token_type,
+                TokenInfo::new(decimals, symbol.clone(), None),
+            );
+        }
+    }
+
+    /// Return the registry entry for a token that can be listed in a new
+    /// market. Panics if the token isn't registered or is blocked.
+    pub fn assert_token_listable(&self, token: &TokenType) -> TokenInfo {
+        let info = _expect!(
+            self.internal_get_token_info(token),
+            "Token is not registered"
+        );
+        _assert_eq!(info.status, TokenStatus::Allowed, "Token is blocked");
+        info
+    }
+
+    /// Panic if the token can't be deposited or the amount exceeds the token's
+    /// deposit cap.
+    pub fn assert_can_deposit(&self, token: &TokenType, amount: Balance) {
+        let info = match self.internal_get_token_info(token) {
+            Some(info) => info,
+            // tokens of existing markets are registered as the markets are
+            // migrated, see [Contract::migrate_markets]
+            None if self.market_migration_cursor.is_some() => return,
+            None => env::panic_str("Token is not registered"),
+        };
+        _assert_eq!(info.status, TokenStatus::Allowed, "Token is blocked");
+        if let Some(cap) = info.deposit_cap {
+            _assert!(amount <= cap, "Deposit exceeds token deposit cap");
+        }
+    }
+
+    /// Fill in missing decimals and symbol for a registered token from its
+    /// metadata. Values set by the owner are never overwritten.
+    pub fn internal_cache_token_metadata(
+        &mut self,
+        token: &TokenType,
+        decimals: u8,
+        symbol: Option<String>,
+    ) {
+        let key = token.key();
+        if let Some(mut info) = self.tokens.get(&key) {
+            if info.decimals.is_some() && (info.symbol.is_some() || symbol.is_none()) {
+                return;
+            }
+            info.decimals = info.decimals.or(Some(decimals));
+            info.symbol = info.symbol.or(symbol);
+            self.tokens.insert(&key, &info);
+        }
+    }
+}
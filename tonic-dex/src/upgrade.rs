@@ -0,0 +1,180 @@
+/// Implements contract code upgrades and migration of the top-level contract
+/// state. Accounts and markets are versioned individually and migrated lazily
+/// when read; see [VAccount] and [VMarket].
+use std::collections::HashSet;
+
+use near_sdk::{Gas, IntoStorageKey};
+
+use crate::*;
+
+/// Gas kept back to finish the `upgrade` call. The rest of the prepaid gas is
+/// forwarded to `migrate`.
+pub const GAS_FOR_UPGRADE: Gas = Gas(20_000_000_000_000);
+
+/// Version of the [Contract] layout written by this code. The contract struct
+/// itself is stored without a version tag, so its version is tracked under a
+/// separate storage key. State written before versioning was added has no
+/// version key and is treated as version 1.
+///
+/// Bump this and add a [VContract] variant when changing [Contract] fields
+/// after a release.
+pub const CURRENT_STATE_VERSION: u8 = 2;
+
+/// Contract layout as of the first release. Do not change this struct.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct ContractV1 {
+    pub owner_id: AccountId,
+    pub accounts: LookupMap<AccountId, VAccount>,
+    pub markets: LookupMap<MarketId, VMarket>,
+    pub market_iter_map: Vector<MarketId>,
+    pub prev_order_sequence_number: SequenceNumber,
+    pub state: ContractState,
+}
+
+/// Contract state as read by `migrate`.
+pub enum VContract {
+    V1(ContractV1),
+    Current(Contract),
+}
+
+impl From<ContractV1> for Contract {
+    fn from(c: ContractV1) -> Self {
+        let market_migration_cursor = if c.market_iter_map.is_empty() {
+            None
+        } else {
+            Some(0)
+        };
+        Self {
+            owner_id: c.owner_id,
+            accounts: c.accounts,
+            markets: c.markets,
+            market_iter_map: c.market_iter_map,
+            prev_order_sequence_number: c.prev_order_sequence_number,
+            state: c.state,
+            tokens: LookupMap::new(StorageKey::Tokens),
+            token_iter_map: Vector::new(StorageKey::TokenIterMap),
+            creator_fee_share_bps: 0,
+            market_stats: LookupMap::new(StorageKey::MarketStats),
+            top_of_book: LookupMap::new(StorageKey::TopOfBook),
+            market_headers: LookupMap::new(StorageKey::MarketHeaders),
+            candles: LookupMap::new(StorageKey::Candles),
+            oracles: LookupMap::new(StorageKey::Oracles),
+            liabilities: LookupMap::new(StorageKey::Liabilities),
+            unclaimed: LookupMap::new(StorageKey::Unclaimed),
+            storage_prepayments: LookupMap::new(StorageKey::StoragePrepayments),
+            registration_pool: 0,
+            markets_by_symbol: LookupMap::new(StorageKey::MarketsBySymbol),
+            markets_by_pair: LookupMap::new(StorageKey::MarketsByPair),
+            markets_by_token: LookupMap::new(StorageKey::MarketsByToken),
+            // markets created before the lookup indexes existed
+            market_migration_cursor,
+        }
+    }
+}
+
+impl From<VContract> for Contract {
+    fn from(v: VContract) -> Self {
+        match v {
+            VContract::V1(c) => c.into(),
+            VContract::Current(c) => c,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Deploy new contract code and call `migrate` on it. The wasm is passed as
+    /// the raw input of the call, not as JSON. Only callable by the contract
+    /// owner.
+    pub fn upgrade(&self) {
+        self.assert_is_owner();
+        let code = _expect!(env::input(), "Missing contract code");
+        let migrate_gas = _expect!(
+            env::prepaid_gas()
+                .0
+                .checked_sub(env::used_gas().0)
+                .and_then(|gas| gas.checked_sub(GAS_FOR_UPGRADE.0)),
+            "Not enough gas attached to migrate"
+        );
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call("migrate".to_string(), vec![], NO_DEPOSIT, Gas(migrate_gas));
+    }
+
+    /// Read contract state written by any previous version and convert it to
+    /// the current layout. Called by `upgrade` after the new code is deployed.
+    ///
+    /// Per-market work is left to `migrate_markets`, so that the gas used here
+    /// doesn't grow with the number of markets.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let version = read_state_version();
+        let state = match version {
+            1 => VContract::V1(_expect!(env::state_read(), "Missing contract state")),
+            CURRENT_STATE_VERSION => {
+                VContract::Current(_expect!(env::state_read(), "Missing contract state"))
+            }
+            _ => env::panic_str("Unknown contract state version"),
+        };
+        write_state_version();
+        state.into()
+    }
+
+    /// Migrate up to `limit` markets left over from `migrate`. Returns the
+    /// number of markets still to migrate. Only callable by the contract
+    /// owner.
+    ///
+    /// Placing orders is blocked until every market is migrated. Owners of
+    /// resting orders are rewritten with their locked balances, the market's
+    /// header is stored under its own key, and the market's tokens are
+    /// registered as allowed if they aren't already.
+    pub fn migrate_markets(&mut self, limit: u64) -> u64 {
+        self.assert_is_owner();
+        let cursor = match self.market_migration_cursor {
+            Some(cursor) => cursor,
+            None => return 0,
+        };
+        let end = std::cmp::min(cursor.saturating_add(limit), self.market_iter_map.len());
+        for index in cursor..end {
+            let market_id = self.market_iter_map.get(index).unwrap();
+            let market = self.internal_unwrap_market(&market_id);
+            self.internal_index_market(&market_id, &market);
+            self.internal_register_market_tokens(&market);
+            self.market_headers
+                .insert(&market_id, &MarketHeader::from(&market));
+            // owners of resting orders were saved before locked balances
+            // were tracked
+            let owners: HashSet<AccountId> = market
+                .orderbook
+                .bids
+                .iter()
+                .chain(market.orderbook.asks.iter())
+                .map(|order| order.owner_id.clone())
+                .collect();
+            for owner_id in owners {
+                self.internal_migrate_account(&owner_id);
+            }
+        }
+        let remaining = self.market_iter_map.len() - end;
+        self.market_migration_cursor = if remaining == 0 { None } else { Some(end) };
+        remaining
+    }
+
+    pub fn get_state_version(&self) -> u8 {
+        read_state_version()
+    }
+}
+
+fn read_state_version() -> u8 {
+    env::storage_read(&StorageKey::StateVersion.into_storage_key())
+        .map(|v| v[0])
+        .unwrap_or(1)
+}
+
+pub(crate) fn write_state_version() {
+    env::storage_write(
+        &StorageKey::StateVersion.into_storage_key(),
+        &[CURRENT_STATE_VERSION],
+    );
+}
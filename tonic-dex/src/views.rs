@@ -5,9 +5,31 @@ use near_sdk::{
     Timestamp,
 };
 
+use std::collections::HashMap;
+
 use crate::market::MarketState;
 use crate::*;
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MarketSummaryView {
+    pub id: MarketId,
+    pub state: MarketState,
+    pub base_token: TokenView,
+    pub quote_token: TokenView,
+}
+
+impl From<MarketSummary> for MarketSummaryView {
+    fn from(m: MarketSummary) -> Self {
+        Self {
+            id: m.id,
+            state: m.state,
+            base_token: m.base_token.into(),
+            quote_token: m.quote_token.into(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(crate = "near_sdk::serde")]
 pub struct MarketView {
@@ -22,6 +44,12 @@ pub struct MarketView {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub total_orders: Option<u32>,
     pub state: MarketState,
+    pub creator_id: Option<AccountId>,
+    pub creator_fee_share_bps: u32,
+    pub creator_fees_accrued: U128,
+    pub symbol: Option<String>,
+    pub tick_size: U128,
+    pub verified: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -30,6 +58,7 @@ pub struct TokenView {
     pub token_type: TokenType,
     pub lot_size: U128,
     pub decimals: u8,
+    pub symbol: Option<String>,
 }
 
 impl From<Token> for TokenView {
@@ -38,6 +67,7 @@ impl From<Token> for TokenView {
             token_type: t.token_type,
             decimals: t.decimals,
             lot_size: t.lot_size.into(),
+            symbol: None,
         }
     }
 }
@@ -52,8 +82,14 @@ impl Market {
     pub fn to_view(&self, price_depth: u8, show_total: bool) -> MarketView {
         MarketView {
             id: self.unwrap_id(),
-            base_token: self.base_token.clone().into(),
-            quote_token: self.quote_token.clone().into(),
+            base_token: TokenView {
+                symbol: self.base_symbol.clone(),
+                ..self.base_token.clone().into()
+            },
+            quote_token: TokenView {
+                symbol: self.quote_symbol.clone(),
+                ..self.quote_token.clone().into()
+            },
             orderbook: orderbook_to_view(
                 &self.orderbook,
                 price_depth,
@@ -73,6 +109,12 @@ impl Market {
                 None
             },
             state: self.state,
+            creator_id: self.creator_id.clone(),
+            creator_fee_share_bps: self.creator_fee_share_bps,
+            creator_fees_accrued: self.creator_fees_accrued.into(),
+            symbol: self.symbol(),
+            tick_size: self.tick_size.into(),
+            verified: self.verified,
         }
     }
 }
@@ -212,6 +254,188 @@ pub fn orderbook_to_view(
     }
 }
 
+/// Orders resting at one price, aggregated.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepthLevelView {
+    pub price: U128,
+    pub total_quantity: U128,
+    pub order_count: u32,
+    /// Total quantity at this and all better prices.
+    pub cumulative_quantity: U128,
+    /// Total value in the quote token at this and all better prices.
+    pub cumulative_notional: U128,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct DepthView {
+    pub bids: Vec<DepthLevelView>,
+    pub asks: Vec<DepthLevelView>,
+}
+
+fn side_to_depth_view(
+    market: &MarketSummary,
+    orderbook: &VecOrderbook,
+    side: Side,
+    levels: u8,
+    max_notional: Option<Balance>,
+) -> Vec<DepthLevelView> {
+    let book_side = match side {
+        Side::Buy => &orderbook.bids,
+        Side::Sell => &orderbook.asks,
+    };
+    let mut cumulative_quantity = 0;
+    let mut cumulative_notional = 0;
+    let mut ret = vec![];
+    for (price, orders) in book_side.take_depth(levels as usize).iter() {
+        let price = market.quote_lots_to_native(*price as LotBalance);
+        let total_quantity =
+            market.base_lots_to_native(orders.iter().map(|o| o.open_qty_lots).sum::<LotBalance>());
+        cumulative_quantity += total_quantity;
+        cumulative_notional += market.notional(price, total_quantity);
+        ret.push(DepthLevelView {
+            price: price.into(),
+            total_quantity: total_quantity.into(),
+            order_count: orders.len() as u32,
+            cumulative_quantity: cumulative_quantity.into(),
+            cumulative_notional: cumulative_notional.into(),
+        });
+        if max_notional.map_or(false, |max| cumulative_notional >= max) {
+            break;
+        }
+    }
+    ret
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TickerView {
+    pub market_id: MarketId,
+    pub best_bid: Option<U128>,
+    pub best_bid_size: Option<U128>,
+    pub best_ask: Option<U128>,
+    pub best_ask_size: Option<U128>,
+    pub mid: Option<U128>,
+    pub spread_bps: Option<u32>,
+    pub last_price: Option<U128>,
+    pub last_quantity: Option<U128>,
+    pub last_trade_timestamp: Option<U64>,
+    /// Base traded over the last 24 hours, with hourly granularity.
+    pub volume_24h: U128,
+    pub quote_volume_24h: U128,
+    pub high_24h: Option<U128>,
+    pub low_24h: Option<U128>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CandleView {
+    pub start: U64,
+    pub open: U128,
+    pub high: U128,
+    pub low: U128,
+    pub close: U128,
+    pub volume: U128,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct TwapView {
+    pub price: U128,
+    /// Window the average was taken over, in nanoseconds. Shorter than
+    /// requested if the market doesn't have enough price history.
+    pub window: U64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PriceView {
+    pub market_id: MarketId,
+    /// Time-weighted average price over [DEFAULT_TWAP_WINDOW_NS].
+    pub price: U128,
+    pub window: U64,
+    /// Half the current spread. `None` if either side of the book is empty.
+    pub confidence: Option<U128>,
+    /// Nanoseconds since the last trade.
+    pub staleness: U64,
+}
+
+/// An order in an orderbook snapshot.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct L3OrderView {
+    pub order_id: OrderId,
+    pub owner: AccountId,
+    pub client_id: Option<ClientId>,
+    pub limit_price: U128,
+    pub price_rank: u32,
+    pub open_qty: U128,
+    pub original_qty: Option<U128>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OrderbookSnapshotView {
+    /// Sequence number of the last order placed when the snapshot was taken.
+    /// Events for later orders apply on top of the snapshot.
+    pub prev_order_sequence_number: U64,
+    /// Orders in priority order, ie, best price first, then oldest first.
+    pub orders: Vec<L3OrderView>,
+    pub has_more: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct MarketOpenOrdersView {
+    pub market_id: MarketId,
+    pub orders: Vec<OpenLimitOrderView>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountView {
+    pub account_id: AccountId,
+    /// Balances available for trading and withdrawal, by token key.
+    pub balances: Vec<(String, U128)>,
+    /// Balances locked in open orders, by token key.
+    pub locked_balances: Vec<(String, U128)>,
+    pub storage_balance: U128,
+    /// Whether storage is covered from the NEAR trading balance.
+    pub auto_storage: bool,
+    /// Bytes of storage paid for by the account, including its orders.
+    pub storage_usage: U64,
+    pub open_orders: Vec<MarketOpenOrdersView>,
+    pub fee_tier: FeeTier,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct LiabilitiesView {
+    pub deposited: U128,
+    pub withdrawn: U128,
+    pub balances: U128,
+    pub locked: U128,
+    pub fees: U128,
+    pub unclaimed: U128,
+    /// Sum of balances, locked balances, fees, and unclaimed funds.
+    pub total: U128,
+}
+
+impl From<Liabilities> for LiabilitiesView {
+    fn from(l: Liabilities) -> Self {
+        Self {
+            deposited: l.deposited.into(),
+            withdrawn: l.withdrawn.into(),
+            balances: l.balances.into(),
+            locked: l.locked.into(),
+            fees: l.fees.into(),
+            unclaimed: l.unclaimed.into(),
+            total: l.total().into(),
+        }
+    }
+}
+
 #[near_bindgen]
 impl Contract {
     pub fn get_market(&self, market_id: MarketId, show_total: Option<bool>) -> Option<MarketView> {
@@ -219,6 +443,8 @@ impl Contract {
             .map(|m| m.to_view(8, show_total.unwrap_or(false)))
     }
 
+    /// Return every order in the top `depth` price levels of each side. Use
+    /// [Contract::get_depth] for one entry per price level.
     pub fn get_orderbook(
         &self,
         market_id: MarketId,
@@ -240,6 +466,230 @@ impl Contract {
         })
     }
 
+    /// Return best bid and ask, last trade, and 24 hour stats for a market.
+    /// Reads cached values only; the orderbook is not loaded.
+    pub fn get_ticker(&self, market_id: MarketId) -> Option<TickerView> {
+        let top = self.internal_get_top_of_book(&market_id)?;
+        let stats = self.internal_get_market_stats(&market_id);
+        let rolling = stats.rolling(env::block_timestamp());
+
+        let bid = top.bid.as_ref().map(|l| l.price);
+        let ask = top.ask.as_ref().map(|l| l.price);
+        let (mid, spread_bps) = match (bid, ask) {
+            (Some(bid), Some(ask)) => {
+                let mid = (bid + ask) / 2;
+                let spread_bps = (U256::from(ask.saturating_sub(bid)) * U256::from(10_000)
+                    / U256::from(mid.max(1)))
+                .as_u32();
+                (Some(mid.into()), Some(spread_bps))
+            }
+            _ => (None, None),
+        };
+
+        Some(TickerView {
+            market_id,
+            best_bid: bid.map(U128),
+            best_bid_size: top.bid.map(|l| l.quantity.into()),
+            best_ask: ask.map(U128),
+            best_ask_size: top.ask.map(|l| l.quantity.into()),
+            mid,
+            spread_bps,
+            last_price: stats.last_price.map(U128),
+            last_quantity: stats.last_price.map(|_| stats.last_quantity.into()),
+            last_trade_timestamp: stats.last_price.map(|_| stats.last_trade_timestamp.into()),
+            volume_24h: rolling.base_volume.into(),
+            quote_volume_24h: rolling.quote_volume.into(),
+            high_24h: rolling.high.map(U128),
+            low_24h: rolling.low.map(U128),
+        })
+    }
+
+    /// Return up to `limit` candles starting at or after `from` (nanoseconds),
+    /// oldest first. Only recent candles are kept: 1 hour of 1-minute candles
+    /// and 1 day of 1-hour candles.
+    pub fn get_candles(
+        &self,
+        market_id: MarketId,
+        resolution: CandleResolution,
+        from: U64,
+        limit: u64,
+    ) -> Vec<CandleView> {
+        let market = match self.internal_get_market_summary(&market_id) {
+            Some(m) => m,
+            None => return vec![],
+        };
+        let price = |lots: LotBalance| U128(lots as u128 * market.quote_token.lot_size);
+        self.internal_get_candles(&market_id, resolution)
+            .candles_from(from.0)
+            .into_iter()
+            .take(limit as usize)
+            .map(|c| CandleView {
+                start: c.start.into(),
+                open: price(c.open),
+                high: price(c.high),
+                low: price(c.low),
+                close: price(c.close),
+                volume: U128(c.volume as u128 * market.base_token.lot_size),
+            })
+            .collect()
+    }
+
+    /// Return the time-weighted average trade price over the last `window`
+    /// nanoseconds. See `market/oracle.rs` for manipulation resistance.
+    pub fn get_twap(&self, market_id: MarketId, window: U64) -> Option<TwapView> {
+        let market = self.internal_get_market_summary(&market_id)?;
+        let (price, window) = self
+            .internal_get_oracle(&market_id)
+            .twap(env::block_timestamp(), window.0)?;
+        Some(TwapView {
+            price: (price as u128 * market.quote_token.lot_size).into(),
+            window: window.into(),
+        })
+    }
+
+    /// Return oracle prices for several markets, in the same order as the
+    /// given IDs. Markets that don't exist or haven't traded are `None`.
+    pub fn get_prices(&self, market_ids: Vec<MarketId>) -> Vec<Option<PriceView>> {
+        let now = env::block_timestamp();
+        market_ids
+            .into_iter()
+            .map(|market_id| {
+                let market = self.internal_get_market_summary(&market_id)?;
+                let oracle = self.internal_get_oracle(&market_id);
+                let (price, window) = oracle.twap(now, DEFAULT_TWAP_WINDOW_NS)?;
+                let top = self.top_of_book.get(&market_id).unwrap_or_default();
+                let confidence = match (top.bid, top.ask) {
+                    (Some(bid), Some(ask)) => Some(U128(ask.price.saturating_sub(bid.price) / 2)),
+                    _ => None,
+                };
+                Some(PriceView {
+                    market_id,
+                    price: (price as u128 * market.quote_token.lot_size).into(),
+                    window: window.into(),
+                    confidence,
+                    staleness: now.saturating_sub(oracle.last_trade).into(),
+                })
+            })
+            .collect()
+    }
+
+    /// Return tickers for several markets, in the same order as the given IDs.
+    pub fn get_tickers(&self, market_ids: Vec<MarketId>) -> Vec<Option<TickerView>> {
+        market_ids
+            .into_iter()
+            .map(|id| self.get_ticker(id))
+            .collect()
+    }
+
+    /// Page through every order on one side of the book in priority order.
+    /// Pass the last order ID of the previous page as `from_order_id` to get
+    /// the next page. If that order has since left the book, no orders are
+    /// returned and the snapshot should be restarted; pages are only
+    /// consistent with each other if their sequence numbers match.
+    ///
+    /// The orderbook doesn't keep original quantities, so they're read from
+    /// the accounts owning the orders on the returned page.
+    pub fn get_orderbook_snapshot(
+        &self,
+        market_id: MarketId,
+        side: Side,
+        from_order_id: Option<OrderId>,
+        limit: u32,
+    ) -> Option<OrderbookSnapshotView> {
+        let (market, orderbook) = self.internal_get_orderbook(&market_id)?;
+        let cursor_price = match from_order_id {
+            Some(order_id) => match orderbook.get_order(order_id) {
+                Some(order) => Some(order.unwrap_price()),
+                None => {
+                    return Some(OrderbookSnapshotView {
+                        prev_order_sequence_number: U64(self.prev_order_sequence_number),
+                        orders: vec![],
+                        has_more: false,
+                    })
+                }
+            },
+            None => None,
+        };
+        let limit = limit as usize;
+
+        // A page ends at most `limit + 1` levels past the cursor's level, so
+        // only that many levels are collected, widening the range until it
+        // reaches past the cursor.
+        let mut depth = limit + 2;
+        let levels = loop {
+            let levels = match side {
+                Side::Buy => orderbook.bids.take_depth(depth),
+                Side::Sell => orderbook.asks.take_depth(depth),
+            };
+            let cursor_level = match cursor_price {
+                Some(price) => levels
+                    .iter()
+                    .position(|(level_price, _)| *level_price as LotBalance == price),
+                None => Some(0),
+            };
+            match cursor_level {
+                _ if levels.len() < depth => break levels,
+                Some(i) if levels.len() > i + limit + 1 => break levels,
+                Some(i) => depth = i + limit + 2,
+                None => depth = depth.saturating_mul(2),
+            }
+        };
+
+        let mut orders = levels.iter().flat_map(|(_, orders)| orders.iter());
+        if let Some(from_order_id) = from_order_id {
+            // skip through the cursor, which is known to be in `levels`
+            orders.any(|o| o.id() == from_order_id);
+        }
+
+        let mut owners: HashMap<AccountId, Option<Account>> = HashMap::new();
+        let mut page: Vec<L3OrderView> = vec![];
+        let mut has_more = false;
+        for order in orders {
+            if page.len() >= limit {
+                has_more = true;
+                break;
+            }
+            let original_qty = owners
+                .entry(order.owner_id.clone())
+                .or_insert_with(|| self.internal_get_account(&order.owner_id))
+                .as_ref()
+                .and_then(|a| a.get_order_info(&market_id, &order.id()))
+                .map(|(lots, _)| U128(market.base_lots_to_native(lots)));
+            page.push(L3OrderView {
+                order_id: order.id(),
+                owner: order.owner_id.clone(),
+                client_id: order.client_id,
+                limit_price: market.quote_lots_to_native(order.unwrap_price()).into(),
+                price_rank: order.unwrap_price_rank(),
+                open_qty: market.base_lots_to_native(order.open_qty_lots).into(),
+                original_qty,
+            });
+        }
+
+        Some(OrderbookSnapshotView {
+            prev_order_sequence_number: U64(self.prev_order_sequence_number),
+            orders: page,
+            has_more,
+        })
+    }
+
+    /// Return up to `levels` price levels per side, with orders at each price
+    /// aggregated. If `max_notional` is set, stop after the level where the
+    /// cumulative notional value of a side reaches it.
+    pub fn get_depth(
+        &self,
+        market_id: MarketId,
+        levels: u8,
+        max_notional: Option<U128>,
+    ) -> Option<DepthView> {
+        let max_notional = max_notional.map(|n| n.0);
+        self.internal_get_orderbook(&market_id)
+            .map(|(m, orderbook)| DepthView {
+                bids: side_to_depth_view(&m, &orderbook, Side::Buy, levels, max_notional),
+                asks: side_to_depth_view(&m, &orderbook, Side::Sell, levels, max_notional),
+            })
+    }
+
     pub fn get_open_orders(
         &self,
         market_id: MarketId,
@@ -278,6 +728,53 @@ impl Contract {
         })
     }
 
+    /// Return an overview of an account across all markets, or `None` if the
+    /// account isn't registered.
+    pub fn get_account(&self, account_id: AccountId) -> Option<AccountView> {
+        let account = self.internal_get_account(&account_id)?;
+        let mut open_orders: Vec<MarketOpenOrdersView> = vec![];
+
+        for market_id in account.open_order_market_ids() {
+            let market = match self.internal_get_market(&market_id) {
+                Some(m) => m,
+                None => continue,
+            };
+            let orders = account
+                .open_orders_iter(&market_id)
+                .filter_map(|(order_id, (original_qty_lots, timestamp))| {
+                    let order = market.orderbook.get_order(order_id)?;
+                    Some(order_to_view(
+                        &order,
+                        market.base_token.lot_size,
+                        market.quote_token.lot_size,
+                        Some(original_qty_lots),
+                        Some(timestamp),
+                    ))
+                })
+                .collect();
+            open_orders.push(MarketOpenOrdersView { market_id, orders });
+        }
+
+        Some(AccountView {
+            account_id,
+            balances: account
+                .get_balances()
+                .into_iter()
+                .map(|(t, b)| (t, U128(b)))
+                .collect(),
+            locked_balances: account
+                .get_locked_balances()
+                .into_iter()
+                .map(|(t, b)| (t, U128(b)))
+                .collect(),
+            storage_balance: account.storage_balance.into(),
+            auto_storage: account.auto_storage,
+            storage_usage: account.storage_usage().into(),
+            open_orders,
+            fee_tier: account.get_fee_tier(),
+        })
+    }
+
     pub fn get_balance(&self, account_id: &AccountId, token_id: &AccountId) -> U128 {
         self.internal_unwrap_account(account_id)
             .get_balance(&token_id.into())
@@ -298,6 +795,42 @@ impl Contract {
             .collect()
     }
 
+    /// Totals owed to users and fee recipients in a token.
+    pub fn get_liabilities(&self, token: String) -> LiabilitiesView {
+        self.internal_get_liabilities(&token).into()
+    }
+
+    /// Funds from failed withdrawals waiting to be claimed, by token key.
+    pub fn get_unclaimed(&self, account_id: AccountId) -> Vec<(String, U128)> {
+        self.unclaimed
+            .get(&account_id)
+            .unwrap_or_default()
+            .balances
+            .into_iter()
+            .map(|(t, b)| (t, U128(b)))
+            .collect()
+    }
+
+    /// Balances locked in open orders, by token key.
+    pub fn get_locked_balances(&self, account_id: &AccountId) -> Vec<(String, U128)> {
+        self.internal_unwrap_account(account_id)
+            .get_locked_balances()
+            .into_iter()
+            .map(|(t, b)| (t, U128::from(b)))
+            .collect()
+    }
+
+    /// Return true if the account's tracked locked balances match its open
+    /// orders.
+    pub fn check_locked_balances(&self, account_id: AccountId) -> bool {
+        let account = self.internal_unwrap_account(&account_id);
+        account
+            .get_locked_balances()
+            .into_iter()
+            .collect::<HashMap<_, _>>()
+            == self.internal_compute_locked_balances(&account)
+    }
+
     pub fn list_markets(&self, from_index: u64, limit: u64) -> Vec<MarketView> {
         (from_index..std::cmp::min(from_index + limit, self.market_iter_map.len()))
             .map(|index| {
@@ -307,6 +840,72 @@ impl Contract {
             .collect()
     }
 
+    /// List market tokens and states without loading orderbooks.
+    pub fn list_market_summaries(&self, from_index: u64, limit: u64) -> Vec<MarketSummaryView> {
+        (from_index..std::cmp::min(from_index + limit, self.market_iter_map.len()))
+            .filter_map(|index| {
+                let id = self.market_iter_map.get(index).unwrap();
+                self.internal_get_market_summary(&id).map(Into::into)
+            })
+            .collect()
+    }
+
+    /// Find markets by base token, quote token and state. All filters are
+    /// optional, eg, pass only `quote_token` and `state: "Active"` to get all
+    /// active markets quoted in that token.
+    pub fn find_markets(
+        &self,
+        base_token: Option<String>,
+        quote_token: Option<String>,
+        state: Option<MarketState>,
+    ) -> Vec<MarketSummaryView> {
+        let base_token = base_token.map(|key| TokenType::from_key(&key));
+        let quote_token = quote_token.map(|key| TokenType::from_key(&key));
+        self.internal_find_markets(base_token.as_ref(), quote_token.as_ref(), state)
+            .into_iter()
+            .map(Into::into)
+            .collect()
+    }
+
+    /// Find markets by ticker symbol, eg, "NEAR/USDC". Matching is
+    /// case-insensitive. Check `verified` when more than one market is
+    /// returned.
+    pub fn get_markets_by_symbol(&self, symbol: String) -> Vec<MarketView> {
+        self.internal_find_markets_by_symbol(&symbol)
+            .iter()
+            .map(|id| self.internal_unwrap_market(id).to_view(8, false))
+            .collect()
+    }
+
+    /// Find markets by base and quote token key, eg, "ft:usdc.near".
+    pub fn get_markets_by_pair(&self, base_token: String, quote_token: String) -> Vec<MarketView> {
+        self.internal_find_markets_by_pair(
+            &TokenType::from_key(&base_token),
+            &TokenType::from_key(&quote_token),
+        )
+        .iter()
+        .map(|id| self.internal_unwrap_market(id).to_view(8, false))
+        .collect()
+    }
+
+    pub fn get_token_info(&self, token: String) -> Option<TokenInfoView> {
+        let token = TokenType::from_key(&token);
+        self.internal_get_token_info(&token)
+            .map(|info| info.to_view(token))
+    }
+
+    pub fn list_tokens(&self, from_index: u64, limit: u64) -> Vec<TokenInfoView> {
+        (from_index..std::cmp::min(from_index + limit, self.token_iter_map.len()))
+            .map(|index| {
+                let key = self.token_iter_map.get(index).unwrap();
+                self.tokens
+                    .get(&key)
+                    .unwrap()
+                    .to_view(TokenType::from_key(&key))
+            })
+            .collect()
+    }
+
     pub fn get_number_of_markets(&self) -> u64 {
         self.market_iter_map.len()
     }
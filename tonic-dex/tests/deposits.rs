@@ -0,0 +1,394 @@
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::json_types::U128;
+use near_sdk::PromiseResult;
+
+use tonic_dex::*;
+
+mod util;
+use util::*;
+
+#[test]
+fn ft_deposit_for_other_account() {
+    let mut contract = setup_contract();
+    let (custodian, trader, _, usdc) = get_accounts();
+    storage_deposit(&mut contract, &trader);
+    register_token(&mut contract, &(&usdc).into());
+
+    set_predecessor_context(usdc.clone());
+    let msg = format!(
+        "{{\"action\":\"Deposit\",\"params\":{{\"account_id\":\"{}\"}}}}",
+        trader
+    );
+    contract.ft_on_transfer(custodian, U128(100), msg);
+    assert_eq!(get_balance(&contract, &trader, (&usdc).into()), 100);
+}
+
+#[test]
+#[should_panic]
+fn ft_deposit_for_unregistered_account() {
+    let mut contract = setup_contract();
+    let (custodian, trader, _, usdc) = get_accounts();
+    register_token(&mut contract, &(&usdc).into());
+
+    set_predecessor_context(usdc);
+    let msg = format!(
+        "{{\"action\":\"Deposit\",\"params\":{{\"account_id\":\"{}\"}}}}",
+        trader
+    );
+    contract.ft_on_transfer(custodian, U128(100), msg);
+}
+
+#[test]
+fn mt_deposit_for_other_account() {
+    let mut contract = setup_contract();
+    let (custodian, trader, mt, _) = get_accounts();
+    storage_deposit(&mut contract, &trader);
+    let token = TokenType::MultiFungibleToken {
+        account_id: mt.clone(),
+        subtoken_id: "1".to_string(),
+    };
+    register_token(&mut contract, &token);
+
+    set_predecessor_context(mt);
+    let msg = format!(
+        "{{\"action\":\"Deposit\",\"params\":{{\"account_id\":\"{}\"}}}}",
+        trader
+    );
+    contract.mt_on_transfer(
+        custodian.clone(),
+        vec![custodian],
+        vec!["1".to_string()],
+        vec![U128(7)],
+        msg,
+    );
+    assert_eq!(get_balance(&contract, &trader, token), 7);
+}
+
+#[test]
+fn deposit_near_for_other_account() {
+    let mut contract = setup_contract();
+    let (custodian, trader, _, _) = get_accounts();
+    storage_deposit(&mut contract, &trader);
+
+    set_deposit_context(custodian, 50);
+    contract.deposit_near_for(trader.clone());
+    assert_eq!(get_balance(&contract, &trader, TokenType::NativeNear), 50);
+}
+
+#[test]
+fn ft_deposit_and_place_order() {
+    let mut contract = setup_contract();
+    let (user_a, user_b, wnear, usdc) = get_accounts();
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(
+        &mut contract,
+        CreateMarketArgs {
+            base_token: TokenType::from_account_id(wnear).key(),
+            base_token_lot_size: 1.into(),
+            quote_token: TokenType::from_account_id(usdc.clone()).key(),
+            quote_token_lot_size: 1.into(),
+            taker_fee_base_rate: 0,
+            maker_rebate_base_rate: 0,
+        },
+        0,
+        0,
+    );
+    storage_deposit(&mut contract, &user_b);
+
+    let msg = near_sdk::serde_json::to_string(&TransferMessage::Actions(vec![Action::NewOrder(
+        NewOrderAction {
+            market_id,
+            params: new_order_params(10, None, 3, Side::Buy, OrderType::Limit, None, None),
+        },
+    )]))
+    .unwrap();
+    set_predecessor_context(usdc.clone());
+    contract.ft_on_transfer(user_b.clone(), U128(100), msg);
+
+    // 30 is locked in the order and the rest stays as exchange balance
+    assert_eq!(get_balance(&contract, &user_b, (&usdc).into()), 70);
+    assert_eq!(contract.get_open_orders(market_id, user_b).len(), 1);
+}
+
+fn setup_transfer_order_market(contract: &mut Contract) -> MarketId {
+    let (user_a, user_b, wnear, usdc) = get_accounts();
+    set_deposit_context(user_a, deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(contract, market_args(&wnear, &usdc, 1), 0, 0);
+    storage_deposit(contract, &user_b);
+    contract.internal_deposit(&user_b, &(&usdc).into(), 1000);
+    contract.internal_deposit(&user_b, &(&wnear).into(), 1000);
+    market_id
+}
+
+fn order_message(market_id: MarketId, side: Side) -> String {
+    near_sdk::serde_json::to_string(&TransferMessage::Action(Action::NewOrder(NewOrderAction {
+        market_id,
+        params: new_order_params(10, None, 3, side, OrderType::Limit, None, None),
+    })))
+    .unwrap()
+}
+
+#[test]
+#[should_panic(expected = "Orders in a transfer message can only spend the transferred amount")]
+fn ft_transfer_order_spends_at_most_amount() {
+    let mut contract = setup_contract();
+    let (_, user_b, _, usdc) = get_accounts();
+    let market_id = setup_transfer_order_market(&mut contract);
+
+    // the order locks 30 but only 10 was transferred
+    set_predecessor_context(usdc);
+    contract.ft_on_transfer(user_b, U128(10), order_message(market_id, Side::Buy));
+}
+
+#[test]
+#[should_panic(expected = "Orders in a transfer message must spend the transferred token")]
+fn ft_transfer_order_spends_transferred_token() {
+    let mut contract = setup_contract();
+    let (_, user_b, _, usdc) = get_accounts();
+    let market_id = setup_transfer_order_market(&mut contract);
+
+    // a sell spends wNEAR, not the transferred USDC
+    set_predecessor_context(usdc);
+    contract.ft_on_transfer(user_b, U128(100), order_message(market_id, Side::Sell));
+}
+
+#[test]
+#[should_panic]
+fn ft_transfer_rejects_transfer_action() {
+    let mut contract = setup_contract();
+    let (user_a, user_b, _, usdc) = get_accounts();
+    setup_transfer_order_market(&mut contract);
+
+    let msg = near_sdk::serde_json::to_string(&TransferMessage::Action(Action::Transfer(
+        TransferAction {
+            receiver_id: user_a,
+            token: TokenType::from_account_id(usdc.clone()).key(),
+            amount: U128(1000),
+            memo: None,
+        },
+    )))
+    .unwrap();
+    set_predecessor_context(usdc);
+    contract.ft_on_transfer(user_b, U128(1), msg);
+}
+
+#[test]
+fn deposit_near_registers_account() {
+    let mut contract = setup_contract();
+    let (user, _, _, _) = get_accounts();
+
+    set_deposit_context(user.clone(), deposits::TENTH_NEAR);
+    contract.deposit_near();
+    let account = contract.internal_unwrap_account(&user);
+    assert!(account.storage_balance > 0);
+    assert_eq!(
+        account.get_balance(&TokenType::NativeNear),
+        deposits::TENTH_NEAR - account.storage_balance
+    );
+}
+
+#[test]
+fn ft_deposit_registers_with_prepayment() {
+    let mut contract = setup_contract();
+    let (user, _, _, usdc) = get_accounts();
+    register_token(&mut contract, &(&usdc).into());
+
+    set_deposit_context(user.clone(), deposits::TENTH_NEAR);
+    contract.prepay_storage(None);
+
+    set_predecessor_context(usdc.clone());
+    contract.ft_on_transfer(
+        user.clone(),
+        U128(100),
+        "{\"action\":\"Register\"}".to_string(),
+    );
+    assert_eq!(get_balance(&contract, &user, (&usdc).into()), 100);
+    assert_eq!(contract.get_storage_prepayment(user.clone()), U128(0));
+    assert_eq!(
+        contract.internal_unwrap_account(&user).storage_balance,
+        deposits::TENTH_NEAR
+    );
+}
+
+/// Register USDC with a minimum deposit of 100 for pool-paid registrations.
+fn register_sponsored_usdc(contract: &mut Contract) {
+    let (owner, _, _, usdc) = get_accounts();
+    set_predecessor_context(owner);
+    register_token(contract, &(&usdc).into());
+    contract
+        .set_token_min_sponsored_deposit(TokenType::from_account_id(usdc).key(), Some(U128(100)));
+}
+
+#[test]
+fn ft_deposit_registers_from_pool() {
+    let mut contract = setup_contract();
+    let (owner, user, _, usdc) = get_accounts();
+    register_sponsored_usdc(&mut contract);
+
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    contract.fund_registration_pool();
+
+    set_predecessor_context(usdc.clone());
+    contract.ft_on_transfer(
+        user.clone(),
+        U128(100),
+        "{\"action\":\"Register\"}".to_string(),
+    );
+    assert_eq!(get_balance(&contract, &user, (&usdc).into()), 100);
+    let storage_balance = contract.internal_unwrap_account(&user).storage_balance;
+    assert_eq!(
+        contract.get_registration_pool(),
+        U128(deposits::TENTH_NEAR - storage_balance)
+    );
+}
+
+#[test]
+fn pool_storage_returns_to_pool_on_unregister() {
+    let mut contract = setup_contract();
+    let (owner, user, _, usdc) = get_accounts();
+    register_sponsored_usdc(&mut contract);
+
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    contract.fund_registration_pool();
+    set_predecessor_context(usdc);
+    contract.ft_on_transfer(
+        user.clone(),
+        U128(100),
+        "{\"action\":\"Register\"}".to_string(),
+    );
+
+    // sponsored storage can't be withdrawn
+    let storage = contract.internal_storage_balance_of(&user).unwrap();
+    assert_eq!(storage.available, U128(0));
+
+    // the account is removed once its balance has been sent
+    assert!(!contract.internal_unregister_account(&user, true));
+    set_promise_results_context(vec![PromiseResult::Successful(b"\"0\"".to_vec())]);
+    assert!(contract.exchange_callback_post_withdraw_many(user.clone(), 1, true));
+    assert!(contract.internal_get_account(&user).is_none());
+    assert_eq!(contract.get_registration_pool(), U128(deposits::TENTH_NEAR));
+}
+
+#[test]
+fn deposit_near_registers_with_prepayment() {
+    let mut contract = setup_contract();
+    let (user, _, _, _) = get_accounts();
+
+    set_deposit_context(user.clone(), deposits::TENTH_NEAR);
+    contract.prepay_storage(None);
+    set_deposit_context(user.clone(), 1000);
+    contract.deposit_near();
+
+    let account = contract.internal_unwrap_account(&user);
+    assert_eq!(account.storage_balance, deposits::TENTH_NEAR);
+    assert_eq!(account.get_balance(&TokenType::NativeNear), 1000);
+    assert_eq!(contract.get_storage_prepayment(user), U128(0));
+}
+
+#[test]
+fn storage_deposit_uses_prepayment() {
+    let mut contract = setup_contract();
+    let (user, _, _, _) = get_accounts();
+
+    set_deposit_context(user.clone(), deposits::TENTH_NEAR);
+    contract.prepay_storage(None);
+    let refund = contract.internal_storage_deposit(&user, true, 1000);
+    assert_eq!(refund, 1000);
+    assert_eq!(
+        contract.internal_unwrap_account(&user).storage_balance,
+        deposits::TENTH_NEAR
+    );
+    assert_eq!(contract.get_storage_prepayment(user), U128(0));
+}
+
+#[test]
+fn withdraw_storage_prepayment() {
+    let mut contract = setup_contract();
+    let (user, _, _, _) = get_accounts();
+
+    set_deposit_context(user.clone(), deposits::TENTH_NEAR);
+    contract.prepay_storage(None);
+    set_deposit_context(user.clone(), 1);
+    assert_eq!(
+        contract.withdraw_storage_prepayment(),
+        U128(deposits::TENTH_NEAR)
+    );
+    assert_eq!(contract.get_storage_prepayment(user), U128(0));
+}
+
+#[test]
+#[should_panic(expected = "insufficient storage balance")]
+fn ft_deposit_register_without_storage() {
+    let mut contract = setup_contract();
+    let (user, _, _, usdc) = get_accounts();
+    register_sponsored_usdc(&mut contract);
+
+    set_predecessor_context(usdc);
+    contract.ft_on_transfer(user, U128(100), "{\"action\":\"Register\"}".to_string());
+}
+
+#[test]
+#[should_panic(expected = "Deposit is too small to register from the registration pool")]
+fn ft_deposit_below_min_not_sponsored() {
+    let mut contract = setup_contract();
+    let (owner, user, _, usdc) = get_accounts();
+    register_sponsored_usdc(&mut contract);
+
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    contract.fund_registration_pool();
+    set_predecessor_context(usdc);
+    contract.ft_on_transfer(user, U128(99), "{\"action\":\"Register\"}".to_string());
+}
+
+#[test]
+#[should_panic(expected = "Deposit is too small to register from the registration pool")]
+fn ft_deposit_without_min_not_sponsored() {
+    let mut contract = setup_contract();
+    let (owner, user, _, usdc) = get_accounts();
+    register_token(&mut contract, &(&usdc).into());
+
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    contract.fund_registration_pool();
+    set_predecessor_context(usdc);
+    contract.ft_on_transfer(user, U128(1000), "{\"action\":\"Register\"}".to_string());
+}
+
+#[test]
+#[should_panic(expected = "Attached deposit must cover the storage of the prepayment")]
+fn prepay_storage_without_deposit() {
+    let mut contract = setup_contract();
+    let (user, _, _, _) = get_accounts();
+
+    set_deposit_context(user, 0);
+    contract.prepay_storage(None);
+}
+
+#[test]
+#[should_panic(expected = "Token is blocked")]
+fn deposit_near_blocked() {
+    let mut contract = setup_contract();
+    let (owner, user, _, _) = get_accounts();
+    storage_deposit(&mut contract, &user);
+
+    set_predecessor_context(owner);
+    contract.register_token(TokenType::NativeNear.key(), None, None, None);
+    contract.set_token_status(TokenType::NativeNear.key(), TokenStatus::Blocked);
+
+    set_deposit_context(user, deposits::TENTH_NEAR);
+    contract.deposit_near();
+}
+
+#[test]
+#[should_panic(expected = "Deposit exceeds token deposit cap")]
+fn deposit_near_for_over_cap() {
+    let mut contract = setup_contract();
+    let (owner, user, _, _) = get_accounts();
+    storage_deposit(&mut contract, &user);
+
+    set_predecessor_context(owner.clone());
+    contract.set_token_deposit_cap(TokenType::NativeNear.key(), Some(U128(1000)));
+
+    set_deposit_context(owner, 1001);
+    contract.deposit_near_for(user);
+}
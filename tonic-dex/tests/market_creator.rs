@@ -0,0 +1,168 @@
+use near_sdk::json_types::U128;
+
+use tonic_dex::*;
+
+mod util;
+use util::*;
+
+const BASE_TOKEN_LOT_SIZE: u128 = 1000000000;
+const QUOTE_TOKEN_LOT_SIZE: u128 = 100000;
+
+fn setup_traded_market(contract: &mut Contract) -> Market {
+    let (maker, taker, wnear, usdc) = get_accounts();
+    let creator = accounts(5);
+    let one_base = 10_u128.pow(16);
+    let one_quote = 10_u128.pow(18);
+
+    set_predecessor_context(maker.clone());
+    contract.set_creator_fee_share(5_000);
+
+    storage_deposit(contract, &maker);
+    storage_deposit(contract, &taker);
+    storage_deposit(contract, &creator);
+    contract.internal_deposit(&maker, &usdc.clone().into(), one_quote * 10);
+    contract.internal_deposit(&taker, &wnear.clone().into(), one_base);
+
+    // Buy 0.2 BASE @ 5 QUOTE, then sell into it
+    set_deposit_context(creator, deposits::TENTH_NEAR);
+    create_market_and_place_orders(
+        contract,
+        CreateMarketArgs {
+            base_token: TokenType::from_account_id(wnear).key(),
+            base_token_lot_size: BASE_TOKEN_LOT_SIZE.into(),
+            quote_token: TokenType::from_account_id(usdc).key(),
+            quote_token_lot_size: QUOTE_TOKEN_LOT_SIZE.into(),
+            taker_fee_base_rate: 20,
+            maker_rebate_base_rate: 2,
+        },
+        vec![
+            (
+                maker,
+                new_order_params(
+                    5 * one_quote,
+                    Some(U128(one_quote * 2)),
+                    one_base / 5,
+                    Side::Buy,
+                    OrderType::Limit,
+                    None,
+                    None,
+                ),
+            ),
+            (
+                taker,
+                new_order_params(
+                    5 * one_quote,
+                    None,
+                    one_base / 5,
+                    Side::Sell,
+                    OrderType::Limit,
+                    None,
+                    None,
+                ),
+            ),
+        ],
+    )
+}
+
+#[test]
+fn creator_fee_share_and_claim() {
+    let mut contract = setup_contract();
+    let (_, _, _, usdc) = get_accounts();
+    let creator = accounts(5);
+    let market = setup_traded_market(&mut contract);
+
+    // 1 QUOTE traded, 20 bps taker fee less 2 bps maker rebate
+    let net_fees = 18 * 10_u128.pow(18) / 10_000;
+    assert_eq!(market.creator_id, Some(creator.clone()));
+    assert_eq!(
+        market.creator_fees_accrued,
+        net_fees / 2,
+        "wrong creator fees"
+    );
+    assert_eq!(
+        market.fees_accrued,
+        net_fees - net_fees / 2,
+        "wrong contract fees"
+    );
+
+    set_predecessor_context(creator.clone());
+    let claimed = contract.claim_creator_fees(market.unwrap_id());
+    assert_eq!(claimed.0, net_fees / 2);
+    assert_eq!(get_balance(&contract, &creator, usdc.into()), net_fees / 2);
+    assert_eq!(
+        contract
+            .internal_unwrap_market(&market.unwrap_id())
+            .creator_fees_accrued,
+        0
+    );
+
+    // creator can set their own market to cancel-only
+    contract.set_market_state(market.unwrap_id(), MarketState::CancelOnly);
+    assert_eq!(
+        contract.internal_unwrap_market(&market.unwrap_id()).state,
+        MarketState::CancelOnly
+    );
+}
+
+#[test]
+#[should_panic(expected = "Market creator can only set a market to active or cancel-only")]
+fn creator_cannot_pause_market() {
+    let mut contract = setup_contract();
+    let market = setup_traded_market(&mut contract);
+
+    set_predecessor_context(accounts(5));
+    contract.set_market_state(market.unwrap_id(), MarketState::Paused);
+}
+
+#[test]
+#[should_panic(expected = "Market state was set by the owner")]
+fn creator_cannot_override_owner_state() {
+    let mut contract = setup_contract();
+    let (owner, _, _, _) = get_accounts();
+    let market = setup_traded_market(&mut contract);
+
+    set_predecessor_context(owner);
+    contract.set_market_state(market.unwrap_id(), MarketState::Paused);
+
+    set_predecessor_context(accounts(5));
+    contract.set_market_state(market.unwrap_id(), MarketState::Active);
+}
+
+#[test]
+#[should_panic(expected = "Method can only be called by the market creator")]
+fn non_creator_cannot_pause_market() {
+    let mut contract = setup_contract();
+    let (_, taker, _, _) = get_accounts();
+    let market = setup_traded_market(&mut contract);
+
+    set_predecessor_context(taker);
+    contract.set_market_state(market.unwrap_id(), MarketState::Paused);
+}
+
+#[test]
+fn delete_market_pays_out_fees() {
+    let mut contract = setup_contract();
+    let (owner, _, _, usdc) = get_accounts();
+    let creator = accounts(5);
+    let market = setup_traded_market(&mut contract);
+    let token: TokenType = usdc.into();
+    let owner_balance = get_balance(&contract, &owner, token.clone());
+
+    // the creator is gone by the time the market is deleted
+    contract.internal_unregister_account(&creator, false);
+    set_predecessor_context(owner.clone());
+    contract.set_market_state(market.unwrap_id(), MarketState::Paused);
+    contract.admin_delete_market(market.unwrap_id());
+
+    assert_eq!(
+        get_balance(&contract, &owner, token.clone()),
+        owner_balance + market.fees_accrued
+    );
+    assert_eq!(
+        contract.get_unclaimed(creator),
+        vec![(token.key(), U128(market.creator_fees_accrued))]
+    );
+    let liabilities = contract.get_liabilities(token.key());
+    assert_eq!(liabilities.fees, U128(0));
+    assert_eq!(liabilities.unclaimed, U128(market.creator_fees_accrued));
+}
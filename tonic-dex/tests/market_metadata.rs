@@ -0,0 +1,122 @@
+use near_sdk::json_types::U128;
+
+use tonic_dex::*;
+
+mod util;
+use util::*;
+
+fn register_with_symbols(contract: &mut Contract) -> MarketId {
+    let (owner, _, wnear, usdc) = get_accounts();
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    contract.register_token(
+        TokenType::from_account_id(wnear.clone()).key(),
+        Some(0),
+        Some("wNEAR".to_string()),
+        None,
+    );
+    contract.register_token(
+        TokenType::from_account_id(usdc.clone()).key(),
+        Some(0),
+        Some("USDC".to_string()),
+        None,
+    );
+    contract.create_market(market_args(&wnear, &usdc, 10))
+}
+
+#[test]
+fn find_markets_by_symbol_and_pair() {
+    let mut contract = setup_contract();
+    let (_, _, wnear, usdc) = get_accounts();
+    let market_id = register_with_symbols(&mut contract);
+
+    let found = contract.get_markets_by_symbol("wnear/usdc".to_string());
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].id, market_id);
+    assert_eq!(found[0].symbol, Some("wNEAR/USDC".to_string()));
+    assert_eq!(found[0].tick_size, U128(10), "tick defaults to quote lot");
+    assert!(!found[0].verified);
+
+    let by_pair = contract.get_markets_by_pair(
+        TokenType::from_account_id(wnear).key(),
+        TokenType::from_account_id(usdc).key(),
+    );
+    assert_eq!(by_pair.len(), 1);
+
+    contract.set_market_verified(market_id, true);
+    contract.set_market_symbols(
+        market_id,
+        Some("NEAR".to_string()),
+        Some("USDC".to_string()),
+    );
+    assert!(contract
+        .get_markets_by_symbol("WNEAR/USDC".to_string())
+        .is_empty());
+    let found = contract.get_markets_by_symbol("NEAR/USDC".to_string());
+    assert_eq!(found.len(), 1);
+    assert!(found[0].verified);
+}
+
+#[test]
+#[should_panic(expected = "Tick size must be a multiple of the quote lot size")]
+fn tick_size_not_multiple_of_lot_size() {
+    let mut contract = setup_contract();
+    let market_id = register_with_symbols(&mut contract);
+    contract.set_market_tick_size(market_id, U128(15));
+}
+
+#[test]
+#[should_panic(expected = "Limit price must be a multiple of the tick size")]
+fn limit_price_off_tick() {
+    let mut contract = setup_contract();
+    let (_, user, _, usdc) = get_accounts();
+    let market_id = register_with_symbols(&mut contract);
+    contract.set_market_tick_size(market_id, U128(100));
+
+    storage_deposit(&mut contract, &user);
+    contract.internal_deposit(&user, &usdc.into(), 10_000);
+    set_predecessor_context(user);
+    contract.new_order(
+        market_id,
+        new_order_params(150, None, 1, Side::Buy, OrderType::Limit, None, None),
+    );
+}
+
+#[test]
+fn find_markets_by_token_and_state() {
+    let mut contract = setup_contract();
+    let (owner, _, wnear, usdc) = get_accounts();
+    let market_id = register_with_symbols(&mut contract);
+
+    // a second market quoting wNEAR, left uninitialized
+    let other = accounts(5);
+    contract.register_token(
+        TokenType::from_account_id(other.clone()).key(),
+        None,
+        None,
+        None,
+    );
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    let other_market_id = contract.create_market(market_args(&other, &wnear, 10));
+
+    let usdc_key = TokenType::from_account_id(usdc).key();
+    let wnear_key = TokenType::from_account_id(wnear).key();
+
+    let quoted_in_usdc = contract.find_markets(None, Some(usdc_key), None);
+    assert_eq!(quoted_in_usdc.len(), 1);
+    assert_eq!(quoted_in_usdc[0].id, market_id);
+
+    let listing_wnear = contract.find_markets(Some(wnear_key.clone()), None, None);
+    assert_eq!(listing_wnear.len(), 1, "wNEAR is only base in one market");
+
+    let quoted_in_wnear = contract.find_markets(None, Some(wnear_key), None);
+    assert_eq!(quoted_in_wnear.len(), 1);
+    assert_eq!(quoted_in_wnear[0].id, other_market_id);
+
+    let active = contract.find_markets(None, None, Some(MarketState::Active));
+    assert_eq!(active.len(), 1);
+    assert_eq!(active[0].id, market_id);
+
+    let summaries = contract.list_market_summaries(0, 10);
+    assert_eq!(summaries.len(), 2);
+    assert_eq!(summaries[1].state, MarketState::Uninitialized);
+}
@@ -1,8 +1,32 @@
 mod util;
-use near_sdk::AccountId;
+use near_sdk::json_types::U128;
+use near_sdk::{AccountId, PromiseResult};
 use tonic_dex::*;
+use tonic_sdk::borsh_size::BorshSize;
 use util::*;
 
+fn create_test_market(
+    contract: &mut Contract,
+    owner: &AccountId,
+    base_token: &AccountId,
+    quote_token: &AccountId,
+) -> MarketId {
+    set_deposit_context(owner.clone(), deposits::TENTH_NEAR);
+    create_and_init_market(
+        contract,
+        CreateMarketArgs {
+            base_token: TokenType::from_account_id(base_token.clone()).key(),
+            base_token_lot_size: 1.into(),
+            quote_token: TokenType::from_account_id(quote_token.clone()).key(),
+            quote_token_lot_size: 1.into(),
+            taker_fee_base_rate: 0,
+            maker_rebate_base_rate: 0,
+        },
+        0,
+        0,
+    )
+}
+
 /// Basically just measures storage use
 #[test]
 fn storage_measurement_cleanup() {
@@ -39,6 +63,15 @@ fn storage_measurement_cleanup() {
                 max_orders_per_account: 10,
                 minimum_bid_bps: 1000,
                 maximum_ask_bps: 30000,
+                creator_id: None,
+                creator_fee_share_bps: 0,
+                creator_fees_accrued: 0,
+                storage_deposit: 0,
+                base_symbol: None,
+                quote_symbol: None,
+                tick_size: 1,
+                verified: false,
+                state_set_by_owner: false,
             },
         );
 
@@ -63,8 +96,15 @@ fn storage_measurement_cleanup() {
 
         // teardown
         contract.internal_cancel_all_orders(&market_id, account_id.clone());
-        contract.internal_unregister_account(&account_id, true);
+        let mut account = contract.internal_unwrap_account(&account_id);
+        account.withdraw(&(&quote_token_id).into(), 2);
+        contract.internal_save_account(&account_id, account);
+        assert!(contract.internal_unregister_account(&account_id, false));
         contract.markets.remove(&market_id);
+        contract.market_headers.remove(&market_id);
+        contract.top_of_book.remove(&market_id);
+        let quote_token: TokenType = (&quote_token_id).into();
+        contract.liabilities.remove(&quote_token.key());
 
         println!(
             "STORAGE USAGE: account {} order {}",
@@ -91,3 +131,161 @@ fn storage_registration_only() {
     account.deposit(&(&token_id).into(), 2);
     contract.internal_save_account(&account_id, account);
 }
+
+/// Storage shortfalls are covered from the NEAR trading balance with
+/// `auto_storage`, and flow back once orders close.
+#[test]
+fn auto_storage_from_near_balance() {
+    let mut contract = setup_contract();
+    let (owner, maker, base_token, quote_token) =
+        (accounts(0), accounts(1), accounts(2), accounts(3));
+
+    let market_id = create_test_market(&mut contract, &owner, &base_token, &quote_token);
+    storage_deposit_registration_only(&mut contract, &maker);
+    set_deposit_context(maker.clone(), 1);
+    contract.set_auto_storage(true);
+
+    let near = TokenType::NativeNear;
+    contract.internal_deposit(&maker, &near, deposits::TENTH_NEAR);
+    contract.internal_deposit(&maker, &(&quote_token).into(), 1000);
+    let account = contract.internal_unwrap_account(&maker);
+    assert!(account.is_storage_covered());
+    assert!(account.auto_storage_balance > 0);
+    let registration_storage = account.storage_balance - account.auto_storage_balance;
+    let total_near = account.storage_balance + account.get_balance(&near);
+
+    set_predecessor_context(maker.clone());
+    let PlaceOrderResultView { id: order_id, .. } = contract.new_order(
+        market_id,
+        new_order_params(10, None, 5, Side::Buy, OrderType::Limit, None, None),
+    );
+    let account = contract.internal_unwrap_account(&maker);
+    assert!(account.is_storage_covered());
+    assert_eq!(
+        account.storage_balance + account.get_balance(&near),
+        total_near
+    );
+    let storage_with_order = account.storage_balance;
+
+    contract.cancel_order(market_id, order_id);
+    let account = contract.internal_unwrap_account(&maker);
+    assert!(account.storage_balance < storage_with_order);
+    assert_eq!(
+        account.storage_balance,
+        account.storage_balance_locked().max(registration_storage)
+    );
+    assert_eq!(
+        account.storage_balance + account.get_balance(&near),
+        total_near
+    );
+    assert_eq!(contract.get_account(maker).unwrap().auto_storage, true);
+}
+
+/// Fails if the computed size of an account record drifts from the storage
+/// it actually uses.
+#[test]
+fn account_size_matches_measured() {
+    let mut contract = setup_contract();
+    let market_id = MarketId::new_unchecked(&b"m".repeat(64));
+    let token: TokenType = (&accounts(2)).into();
+
+    for account_id in [AccountId::new_unchecked("0".repeat(64)), accounts(1)] {
+        let mut account = Account::new(&account_id);
+        account.storage_balance = DEFAULT_STORAGE_BALANCE_YOCTO;
+        account.deposit(&TokenType::NativeNear, 1);
+        account.deposit(&token, 2);
+        account.lock(&token, 1);
+        account.save_new_order_info(&market_id, 1, 1, 10);
+        account.save_new_order_info(&market_id, 2, 1, 10);
+        let computed = account.borsh_size();
+
+        let measured = measure_storage_increase!({
+            contract.internal_save_account(&account_id, account);
+        });
+        assert_eq!(computed, measured, "account size drift");
+    }
+}
+
+/// Fails if the orderbook storage charged for an order drifts from the storage
+/// it actually uses.
+#[test]
+fn order_storage_matches_measured() {
+    let mut contract = setup_contract();
+    let (owner, maker, base_token, quote_token) =
+        (accounts(0), accounts(1), accounts(2), accounts(3));
+    let market_id = create_test_market(&mut contract, &owner, &base_token, &quote_token);
+    storage_deposit(&mut contract, &maker);
+    contract.internal_deposit(&maker, &(&quote_token).into(), 1000);
+
+    set_predecessor_context(maker.clone());
+    let account_size_before = contract.internal_unwrap_account(&maker).borsh_size();
+    let mut order_id = None;
+    let measured = measure_storage_increase!({
+        order_id = Some(
+            contract
+                .new_order(
+                    market_id,
+                    new_order_params(10, None, 5, Side::Buy, OrderType::Limit, None, None),
+                )
+                .id,
+        );
+    });
+    let account = contract.internal_unwrap_account(&maker);
+    assert!(account.get_orderbook_storage() > 0);
+    assert_eq!(
+        account.get_orderbook_storage(),
+        measured - (account.borsh_size() - account_size_before),
+        "order size drift"
+    );
+
+    contract.cancel_order(market_id, order_id.unwrap());
+    let account = contract.internal_unwrap_account(&maker);
+    assert_eq!(account.get_orderbook_storage(), 0);
+}
+
+#[test]
+fn storage_unregister_force() {
+    let mut contract = setup_contract();
+    let (owner, maker, base_token, quote_token) =
+        (accounts(0), accounts(1), accounts(2), accounts(3));
+    let market_id = create_test_market(&mut contract, &owner, &base_token, &quote_token);
+    storage_deposit(&mut contract, &maker);
+    contract.internal_deposit(&maker, &(&quote_token).into(), 1000);
+
+    set_predecessor_context(maker.clone());
+    let PlaceOrderResultView { id: order_id, .. } = contract.new_order(
+        market_id,
+        new_order_params(10, None, 5, Side::Buy, OrderType::Limit, None, None),
+    );
+
+    set_deposit_context(maker.clone(), 1);
+    // balances are sent first, and the account is removed once they arrive
+    assert!(!contract.storage_unregister(Some(true)));
+    assert!(contract.internal_get_account(&maker).is_some());
+    let market = contract.internal_unwrap_market(&market_id);
+    assert!(market.orderbook.get_order(order_id).is_none());
+    set_promise_results_context(vec![PromiseResult::Successful(b"\"0\"".to_vec())]);
+    assert!(contract.exchange_callback_post_withdraw_many(maker.clone(), 1, true));
+    assert!(contract.internal_get_account(&maker).is_none());
+
+    let liabilities =
+        contract.get_liabilities(TokenType::from_account_id(quote_token.clone()).key());
+    assert_eq!(liabilities.balances, U128(0));
+    assert_eq!(liabilities.locked, U128(0));
+    assert_eq!(liabilities.withdrawn, U128(1000));
+
+    // already unregistered
+    assert!(!contract.storage_unregister(Some(true)));
+}
+
+#[test]
+#[should_panic(expected = "account not empty")]
+fn storage_unregister_not_empty() {
+    let mut contract = setup_contract();
+    let maker = accounts(1);
+    storage_deposit(&mut contract, &maker);
+    contract.internal_deposit(&maker, &(&accounts(3)).into(), 1000);
+
+    set_deposit_context(maker, 1);
+    contract.storage_unregister(None);
+}
@@ -0,0 +1,105 @@
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::json_types::U128;
+
+use tonic_dex::*;
+
+mod util;
+use util::*;
+
+#[test]
+#[should_panic(expected = "Token is not registered")]
+fn create_market_unregistered_token() {
+    let mut contract = setup_contract();
+    let (owner, _, wnear, usdc) = get_accounts();
+
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    contract.register_token(
+        TokenType::from_account_id(usdc.clone()).key(),
+        None,
+        None,
+        None,
+    );
+    contract.create_market(market_args(&wnear, &usdc, 1));
+}
+
+#[test]
+#[should_panic(expected = "Token is blocked")]
+fn create_market_blocked_token() {
+    let mut contract = setup_contract();
+    let (owner, _, wnear, usdc) = get_accounts();
+
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    for token in [&wnear, &usdc] {
+        contract.register_token(
+            TokenType::from_account_id(token.clone()).key(),
+            None,
+            None,
+            None,
+        );
+    }
+    contract.set_token_status(
+        TokenType::from_account_id(wnear.clone()).key(),
+        TokenStatus::Blocked,
+    );
+    contract.create_market(market_args(&wnear, &usdc, 1));
+}
+
+#[test]
+fn create_market_with_known_decimals_is_active() {
+    let mut contract = setup_contract();
+    let (owner, _, wnear, usdc) = get_accounts();
+
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    contract.register_token(
+        TokenType::from_account_id(wnear.clone()).key(),
+        Some(0),
+        Some("wNEAR".to_string()),
+        None,
+    );
+    contract.register_token(
+        TokenType::from_account_id(usdc.clone()).key(),
+        Some(0),
+        Some("USDC".to_string()),
+        None,
+    );
+    let market_id = contract.create_market(market_args(&wnear, &usdc, 1));
+
+    let market = contract.get_market(market_id, None).unwrap();
+    assert_eq!(market.state, MarketState::Active, "market should be active");
+    assert_eq!(contract.list_tokens(0, 10).len(), 2);
+}
+
+#[test]
+fn metadata_callback_caches_decimals() {
+    let mut contract = setup_contract();
+    let (owner, _, wnear, usdc) = get_accounts();
+
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(&mut contract, market_args(&wnear, &usdc, 1), 0, 0);
+    assert!(contract.get_market(market_id, None).is_some());
+
+    let info = contract
+        .get_token_info(TokenType::from_account_id(wnear).key())
+        .unwrap();
+    assert_eq!(info.decimals, Some(0), "decimals not cached");
+}
+
+#[test]
+#[should_panic(expected = "Deposit exceeds token deposit cap")]
+fn deposit_over_cap() {
+    let mut contract = setup_contract();
+    let (owner, user, _, usdc) = get_accounts();
+
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    contract.register_token(
+        TokenType::from_account_id(usdc.clone()).key(),
+        None,
+        None,
+        Some(U128(100)),
+    );
+    storage_deposit(&mut contract, &user);
+
+    set_predecessor_context(usdc);
+    contract.ft_on_transfer(user.clone(), U128(100), "".to_string());
+    contract.ft_on_transfer(user, U128(101), "".to_string());
+}
@@ -685,6 +685,69 @@ fn test_immediate_or_canel() {
     assert_eq!(buyer_usdc_balance, 9 * one_quote);
 }
 
+#[test]
+fn test_locked_balances() {
+    let mut contract = setup_contract();
+    let owner = util::accounts(0);
+    let maker = accounts(1);
+    let taker = accounts(4);
+    let base_token = accounts(2);
+    let quote_token = accounts(3);
+
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(
+        &mut contract,
+        CreateMarketArgs {
+            base_token: TokenType::from_account_id(base_token.clone()).key(),
+            base_token_lot_size: 1.into(),
+            quote_token: TokenType::from_account_id(quote_token.clone()).key(),
+            quote_token_lot_size: 1.into(),
+            taker_fee_base_rate: 0,
+            maker_rebate_base_rate: 0,
+        },
+        0,
+        0,
+    );
+    storage_deposit(&mut contract, &maker);
+    storage_deposit(&mut contract, &taker);
+    contract.internal_deposit(&maker, &(&quote_token).into(), 1000);
+    contract.internal_deposit(&taker, &(&base_token).into(), 100);
+
+    let quote_key = TokenType::from_account_id(quote_token.clone()).key();
+    set_predecessor_context(maker.clone());
+    let PlaceOrderResultView { id: order_id, .. } = contract.new_order(
+        market_id,
+        NewOrderParams {
+            limit_price: Some(U128::from(10)),
+            max_spend: None,
+            quantity: U128::from(5),
+            side: Side::Buy,
+            order_type: OrderType::Limit,
+            client_id: None,
+            referrer_id: None,
+        },
+    );
+    assert_eq!(
+        contract.get_locked_balances(&maker),
+        vec![(quote_key.clone(), U128(50))]
+    );
+    assert!(contract.check_locked_balances(maker.clone()));
+
+    set_predecessor_context(taker.clone());
+    contract.new_order(market_id, market_order_params(None, U128(2), Side::Sell));
+    assert_eq!(
+        contract.get_locked_balances(&maker),
+        vec![(quote_key, U128(30))]
+    );
+    assert!(contract.check_locked_balances(maker.clone()));
+    assert!(contract.get_locked_balances(&taker).is_empty());
+
+    set_predecessor_context(maker.clone());
+    contract.cancel_order(market_id, order_id);
+    assert!(contract.get_locked_balances(&maker).is_empty());
+    assert!(contract.check_locked_balances(maker));
+}
+
 #[test]
 fn test_cancel_order() {
     let mut contract = setup_contract();
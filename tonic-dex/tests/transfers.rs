@@ -0,0 +1,463 @@
+use near_sdk::json_types::U128;
+use near_sdk::{env, Gas, PromiseOrValue, PromiseResult};
+
+use tonic_dex::*;
+
+mod util;
+use util::*;
+
+fn setup_funded_accounts() -> (
+    Contract,
+    near_sdk::AccountId,
+    near_sdk::AccountId,
+    TokenType,
+) {
+    let mut contract = setup_contract();
+    let (user_a, user_b, _, usdc) = get_accounts();
+    storage_deposit(&mut contract, &user_a);
+    storage_deposit(&mut contract, &user_b);
+    let token: TokenType = (&usdc).into();
+    contract.internal_deposit(&user_a, &token, 100);
+    (contract, user_a, user_b, token)
+}
+
+#[test]
+fn transfer() {
+    let (mut contract, user_a, user_b, token) = setup_funded_accounts();
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.transfer(
+        user_b.clone(),
+        token.key(),
+        U128(40),
+        Some("rent".to_string()),
+    );
+    assert_eq!(get_balance(&contract, &user_a, token.clone()), 60);
+    assert_eq!(get_balance(&contract, &user_b, token.clone()), 40);
+    assert_eq!(contract.get_liabilities(token.key()).balances, U128(100));
+}
+
+#[test]
+fn transfer_action() {
+    let (mut contract, user_a, user_b, token) = setup_funded_accounts();
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.execute(vec![Action::Transfer(TransferAction {
+        receiver_id: user_b.clone(),
+        token: token.key(),
+        amount: U128(100),
+        memo: None,
+    })]);
+    assert_eq!(get_balance(&contract, &user_a, token.clone()), 0);
+    assert_eq!(get_balance(&contract, &user_b, token), 100);
+}
+
+#[test]
+#[should_panic]
+fn transfer_more_than_balance() {
+    let (mut contract, user_a, user_b, token) = setup_funded_accounts();
+
+    set_deposit_context(user_a, 1);
+    contract.transfer(user_b, token.key(), U128(101), None);
+}
+
+#[test]
+#[should_panic(expected = "Receiver has insufficient storage balance")]
+fn transfer_to_account_without_storage() {
+    let mut contract = setup_contract();
+    let (user_a, user_b, _, usdc) = get_accounts();
+    storage_deposit(&mut contract, &user_a);
+    storage_deposit_registration_only(&mut contract, &user_b);
+    let token: TokenType = (&usdc).into();
+    contract.internal_deposit(&user_a, &token, 100);
+
+    set_deposit_context(user_a, 1);
+    contract.transfer(user_b, token.key(), U128(1), None);
+}
+
+#[test]
+fn withdraw_transfer_call_partial_refund() {
+    let (mut contract, user_a, user_b, token) = setup_funded_accounts();
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw(
+        token.key(),
+        U128(100),
+        Some(user_b),
+        Some("deposit".to_string()),
+        Some("{}".to_string()),
+        None,
+    );
+    assert_eq!(get_balance(&contract, &user_a, token.clone()), 0);
+    assert_eq!(contract.get_liabilities(token.key()).withdrawn, U128(100));
+
+    // the receiver kept 30 and refunded the rest
+    set_promise_results_context(vec![PromiseResult::Successful(b"\"30\"".to_vec())]);
+    let refund =
+        contract.exchange_callback_post_withdraw(&token, user_a.clone(), U128(100), Some(true));
+    assert_eq!(refund, U128(70));
+    assert_eq!(get_balance(&contract, &user_a, token.clone()), 70);
+    assert_eq!(contract.get_liabilities(token.key()).withdrawn, U128(30));
+}
+
+#[test]
+fn withdraw_failed_transfer_refunds_all() {
+    let (mut contract, user_a, _, token) = setup_funded_accounts();
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw(token.key(), U128(100), None, None, None, None);
+
+    set_promise_results_context(vec![PromiseResult::Failed]);
+    let refund = contract.exchange_callback_post_withdraw(&token, user_a.clone(), U128(100), None);
+    assert_eq!(refund, U128(100));
+    assert_eq!(get_balance(&contract, &user_a, token), 100);
+}
+
+#[test]
+fn failed_withdrawal_without_storage_is_credited() {
+    let (mut contract, user_a, _, token) = setup_funded_accounts();
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw(token.key(), U128(100), None, None, None, None);
+    // the account no longer has storage to hold the token balance
+    let mut account = contract.internal_unwrap_account(&user_a);
+    account.storage_balance = account.storage_balance_locked();
+    contract.internal_save_account(&user_a, account);
+
+    set_promise_results_context(vec![PromiseResult::Failed]);
+    let refund = contract.exchange_callback_post_withdraw(&token, user_a.clone(), U128(100), None);
+    assert_eq!(refund, U128(100));
+    // the account pays for holding the refund
+    assert_eq!(get_balance(&contract, &user_a, token.clone()), 100);
+    assert!(contract.get_unclaimed(user_a.clone()).is_empty());
+    let storage = contract.internal_storage_balance_of(&user_a).unwrap();
+    assert_eq!(storage.available, U128(0));
+}
+
+#[test]
+fn failed_withdrawal_to_unregistered_account_is_escrowed() {
+    let (mut contract, user_a, _, token) = setup_funded_accounts();
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    contract.fund_registration_pool();
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw(token.key(), U128(100), None, None, None, None);
+    contract.internal_unregister_account(&user_a, false);
+
+    set_promise_results_context(vec![PromiseResult::Failed]);
+    contract.exchange_callback_post_withdraw(&token, user_a.clone(), U128(100), None);
+    assert_eq!(
+        contract.get_unclaimed(user_a.clone()),
+        vec![(token.key(), U128(100))]
+    );
+    assert_eq!(contract.get_liabilities(token.key()).unclaimed, U128(100));
+    // the escrow entry is paid from the registration pool
+    assert!(contract.get_registration_pool().0 < deposits::TENTH_NEAR);
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.claim_unclaimed(token.key());
+    assert!(contract.get_unclaimed(user_a).is_empty());
+    assert_eq!(contract.get_liabilities(token.key()).unclaimed, U128(0));
+    assert_eq!(contract.get_registration_pool(), U128(deposits::TENTH_NEAR));
+}
+
+/// The unit test runtime only charges for host calls, not wasm execution, so
+/// callbacks must measure well under their budget to leave room for it.
+fn assert_within_gas_budget(used: u64, budget: Gas) {
+    assert!(
+        used < budget.0 / 2,
+        "used {} of a {} gas budget",
+        used,
+        budget.0
+    );
+}
+
+#[test]
+fn failed_withdrawal_escrow_fits_resolve_gas() {
+    let (mut contract, user_a, _, token) = setup_funded_accounts();
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    contract.fund_registration_pool();
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw(token.key(), U128(100), None, None, None, None);
+    contract.internal_unregister_account(&user_a, false);
+
+    // worst case: a refund for an account that no longer exists is escrowed
+    set_promise_results_context(vec![PromiseResult::Failed]);
+    let start = env::used_gas().0;
+    contract.exchange_callback_post_withdraw(&token, user_a.clone(), U128(100), None);
+    assert_within_gas_budget(env::used_gas().0 - start, GAS_FOR_RESOLVE_TRANSFER);
+    assert_eq!(
+        contract.get_unclaimed(user_a),
+        vec![(token.key(), U128(100))]
+    );
+}
+
+const MIN_FT_STORAGE: u128 = 1_250_000_000_000_000_000_000;
+
+fn unregistered_receiver_results() -> Vec<PromiseResult> {
+    let bounds = format!("{{\"min\":\"{}\",\"max\":null}}", MIN_FT_STORAGE);
+    vec![
+        PromiseResult::Successful(b"null".to_vec()),
+        PromiseResult::Successful(bounds.into_bytes()),
+    ]
+}
+
+#[test]
+fn withdraw_registers_receiver_from_storage_balance() {
+    let (mut contract, user_a, user_b, token) = setup_funded_accounts();
+    let token_id = get_accounts().3;
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw(
+        token.key(),
+        U128(100),
+        Some(user_b.clone()),
+        None,
+        None,
+        Some(StoragePayment::StorageBalance),
+    );
+    assert_eq!(get_balance(&contract, &user_a, token.clone()), 0);
+
+    let storage_before = contract.internal_unwrap_account(&user_a).storage_balance;
+    set_promise_results_context(unregistered_receiver_results());
+    contract.on_receiver_storage_checked(
+        user_a.clone(),
+        user_b,
+        token_id,
+        U128(100),
+        None,
+        None,
+        StoragePayment::StorageBalance,
+    );
+    assert_eq!(
+        contract.internal_unwrap_account(&user_a).storage_balance,
+        storage_before - MIN_FT_STORAGE
+    );
+    assert_eq!(contract.get_liabilities(token.key()).withdrawn, U128(0));
+
+    set_promise_results_context(vec![PromiseResult::Successful(
+        b"{\"total\":\"1\",\"available\":\"0\"}".to_vec(),
+    )]);
+    contract.on_receiver_storage_deposit(
+        user_a,
+        user_b,
+        get_accounts().3,
+        U128(100),
+        None,
+        None,
+        StoragePayment::StorageBalance,
+        U128(MIN_FT_STORAGE),
+    );
+    assert_eq!(contract.get_liabilities(token.key()).withdrawn, U128(100));
+}
+
+#[test]
+fn failed_receiver_storage_deposit_is_refunded() {
+    let (mut contract, user_a, user_b, token) = setup_funded_accounts();
+    let token_id = get_accounts().3;
+    contract.internal_deposit(&user_a, &TokenType::NativeNear, MIN_FT_STORAGE);
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw(
+        token.key(),
+        U128(100),
+        Some(user_b.clone()),
+        None,
+        None,
+        Some(StoragePayment::ExchangeBalance),
+    );
+    set_promise_results_context(unregistered_receiver_results());
+    contract.on_receiver_storage_checked(
+        user_a.clone(),
+        user_b.clone(),
+        token_id.clone(),
+        U128(100),
+        None,
+        None,
+        StoragePayment::ExchangeBalance,
+    );
+    assert_eq!(get_balance(&contract, &user_a, TokenType::NativeNear), 0);
+    assert_eq!(
+        contract
+            .get_liabilities(TokenType::NativeNear.key())
+            .withdrawn,
+        U128(MIN_FT_STORAGE)
+    );
+
+    // the token contract rejected the deposit and returned the NEAR
+    set_promise_results_context(vec![PromiseResult::Failed]);
+    let credited = contract.on_receiver_storage_deposit(
+        user_a.clone(),
+        user_b,
+        token_id,
+        U128(100),
+        None,
+        None,
+        StoragePayment::ExchangeBalance,
+        U128(MIN_FT_STORAGE),
+    );
+    assert!(matches!(credited, PromiseOrValue::Value(U128(100))));
+    assert_eq!(get_balance(&contract, &user_a, token.clone()), 100);
+    assert_eq!(
+        get_balance(&contract, &user_a, TokenType::NativeNear),
+        MIN_FT_STORAGE
+    );
+    assert_eq!(contract.get_liabilities(token.key()).withdrawn, U128(0));
+    assert_eq!(
+        contract
+            .get_liabilities(TokenType::NativeNear.key())
+            .withdrawn,
+        U128(0)
+    );
+}
+
+#[test]
+fn failed_receiver_storage_deposit_escrow_fits_callback_gas() {
+    let (mut contract, user_a, user_b, token) = setup_funded_accounts();
+    let token_id = get_accounts().3;
+    contract.internal_deposit(&user_a, &TokenType::NativeNear, MIN_FT_STORAGE);
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    contract.fund_registration_pool();
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw(
+        token.key(),
+        U128(100),
+        Some(user_b.clone()),
+        None,
+        None,
+        Some(StoragePayment::ExchangeBalance),
+    );
+    set_promise_results_context(unregistered_receiver_results());
+    contract.on_receiver_storage_checked(
+        user_a.clone(),
+        user_b.clone(),
+        token_id.clone(),
+        U128(100),
+        None,
+        None,
+        StoragePayment::ExchangeBalance,
+    );
+    contract.internal_unregister_account(&user_a, false);
+
+    // worst case: both the payment and the withdrawal are escrowed
+    set_promise_results_context(vec![PromiseResult::Failed]);
+    let start = env::used_gas().0;
+    contract.on_receiver_storage_deposit(
+        user_a.clone(),
+        user_b,
+        token_id,
+        U128(100),
+        None,
+        None,
+        StoragePayment::ExchangeBalance,
+        U128(MIN_FT_STORAGE),
+    );
+    assert_within_gas_budget(env::used_gas().0 - start, GAS_FOR_RECEIVER_STORAGE_CALLBACK);
+    assert_eq!(contract.get_unclaimed(user_a).len(), 2);
+}
+
+#[test]
+fn receiver_storage_unpaid_by_account_short_of_storage_is_credited() {
+    let (mut contract, user_a, user_b, token) = setup_funded_accounts();
+    let token_id = get_accounts().3;
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw(
+        token.key(),
+        U128(100),
+        Some(user_b.clone()),
+        None,
+        None,
+        Some(StoragePayment::StorageBalance),
+    );
+    // storage balance is below what the account uses, eg, after an upgrade
+    rewrite_account_as_v1(&mut contract, &user_a, 0);
+
+    set_promise_results_context(unregistered_receiver_results());
+    let credited = contract.on_receiver_storage_checked(
+        user_a.clone(),
+        user_b,
+        token_id,
+        U128(100),
+        None,
+        None,
+        StoragePayment::StorageBalance,
+    );
+    assert!(matches!(credited, PromiseOrValue::Value(U128(100))));
+    assert_eq!(get_balance(&contract, &user_a, token.clone()), 100);
+    assert!(contract.get_unclaimed(user_a).is_empty());
+    assert_eq!(contract.get_liabilities(token.key()).withdrawn, U128(0));
+}
+
+#[test]
+fn withdraw_refunded_if_receiver_storage_unpaid() {
+    let (mut contract, user_a, user_b, token) = setup_funded_accounts();
+    let token_id = get_accounts().3;
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw(
+        token.key(),
+        U128(100),
+        Some(user_b.clone()),
+        None,
+        None,
+        Some(StoragePayment::ExchangeBalance),
+    );
+
+    // user_a has no NEAR exchange balance to pay with
+    set_promise_results_context(unregistered_receiver_results());
+    contract.on_receiver_storage_checked(
+        user_a.clone(),
+        user_b,
+        token_id,
+        U128(100),
+        None,
+        None,
+        StoragePayment::ExchangeBalance,
+    );
+    assert_eq!(get_balance(&contract, &user_a, token.clone()), 100);
+    assert_eq!(contract.get_liabilities(token.key()).withdrawn, U128(0));
+}
+
+#[test]
+fn withdraw_many_and_unregister() {
+    let (mut contract, user_a, _, token) = setup_funded_accounts();
+    contract.internal_deposit(&user_a, &TokenType::NativeNear, 5);
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw_many(
+        vec![
+            (token.clone(), Some(U128(40))),
+            (TokenType::NativeNear, None),
+        ],
+        Some(true),
+    );
+    assert_eq!(get_balance(&contract, &user_a, token.clone()), 60);
+    assert_eq!(get_balance(&contract, &user_a, TokenType::NativeNear), 0);
+
+    // not unregistered while a balance remains
+    set_promise_results_context(vec![
+        PromiseResult::Successful(b"\"0\"".to_vec()),
+        PromiseResult::Successful(b"\"0\"".to_vec()),
+    ]);
+    assert!(!contract.exchange_callback_post_withdraw_many(user_a.clone(), 2, true));
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw_many(vec![(token, None)], Some(true));
+    set_promise_results_context(vec![PromiseResult::Successful(b"\"0\"".to_vec())]);
+    assert!(contract.exchange_callback_post_withdraw_many(user_a.clone(), 1, true));
+    assert!(contract.internal_get_account(&user_a).is_none());
+}
+
+#[test]
+fn withdraw_many_keeps_account_after_failed_transfer() {
+    let (mut contract, user_a, _, token) = setup_funded_accounts();
+
+    set_deposit_context(user_a.clone(), 1);
+    contract.withdraw_many(vec![(token, None)], Some(true));
+    set_promise_results_context(vec![PromiseResult::Successful(b"\"100\"".to_vec())]);
+    assert!(!contract.exchange_callback_post_withdraw_many(user_a.clone(), 1, true));
+    assert!(contract.internal_get_account(&user_a).is_some());
+}
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::collections::{LookupMap, Vector};
+use near_sdk::json_types::U128;
+use near_sdk::{env, testing_env, AccountId};
+use tonic_dex::*;
+
+mod util;
+use util::*;
+
+/// Write contract state, an account, and a market in the V1 layout, then
+/// migrate and check that everything reads back in the current layout.
+#[test]
+fn migrate_from_v1_fixtures() {
+    let mut context = get_context(vec![]);
+    context.predecessor_account_id = context.current_account_id.clone();
+    testing_env!(context);
+
+    let (owner, user, wnear, usdc) = get_accounts();
+    let market_id = MarketId::new_unchecked(&b"m".repeat(64));
+
+    let mut accounts: LookupMap<AccountId, VAccount> = LookupMap::new(StorageKey::Accounts);
+    let mut balances = HashMap::new();
+    balances.insert(TokenType::from_account_id(usdc.clone()).key(), 100);
+    accounts.insert(
+        &user,
+        &VAccount::V1(AccountV1 {
+            balances,
+            open_orders: HashMap::new(),
+            storage_balance: DEFAULT_STORAGE_BALANCE_YOCTO,
+        }),
+    );
+
+    let mut markets: LookupMap<MarketId, VMarket> = LookupMap::new(StorageKey::Markets);
+    markets.insert(
+        &market_id,
+        &VMarket::V1(MarketV1 {
+            state: MarketState::Active,
+            base_token: Token {
+                token_type: wnear.clone().into(),
+                lot_size: 1,
+                decimals: 0,
+            },
+            quote_token: Token {
+                token_type: usdc.clone().into(),
+                lot_size: 1,
+                decimals: 0,
+            },
+            orderbook: Orderbook::default(),
+            taker_fee_base_rate: 20,
+            maker_rebate_base_rate: 2,
+            max_orders_per_account: 10,
+            fees_accrued: 7,
+            minimum_bid_bps: DEFAULT_MIN_MULTIPLIER_BPS,
+            maximum_ask_bps: DEFAULT_MAX_MULTIPLIER_BPS,
+        }),
+    );
+
+    let mut market_iter_map = Vector::new(StorageKey::MarketIterMap);
+    market_iter_map.push(&market_id);
+
+    env::state_write(&ContractV1 {
+        owner_id: owner.clone(),
+        accounts,
+        markets,
+        market_iter_map,
+        prev_order_sequence_number: 42,
+        state: ContractState::Active,
+    });
+
+    let mut contract = Contract::migrate();
+    assert_eq!(contract.get_state_version(), CURRENT_STATE_VERSION);
+    assert_eq!(contract.get_owner(), owner);
+    assert_eq!(contract.prev_order_sequence_number, 42);
+    assert_eq!(contract.get_number_of_markets(), 1);
+    set_predecessor_context(owner.clone());
+    assert_eq!(contract.migrate_markets(0), 1);
+    // summaries fall back to the market record until the market is migrated
+    assert!(contract.market_headers.get(&market_id).is_none());
+    assert_eq!(
+        contract.list_market_summaries(0, 10)[0].state,
+        MarketState::Active
+    );
+    assert_eq!(contract.migrate_markets(10), 0);
+    assert_eq!(contract.market_migration_cursor, None);
+    assert_eq!(
+        contract.market_headers.get(&market_id).unwrap().state,
+        MarketState::Active
+    );
+
+    // records are converted on read
+    let market = contract.internal_unwrap_market(&market_id);
+    assert_eq!(market.fees_accrued, 7);
+    assert_eq!(market.taker_fee_base_rate, 20);
+    assert_eq!(market.tick_size, 1);
+    assert_eq!(get_balance(&contract, &user, usdc.clone().into()), 100);
+    assert_eq!(
+        contract
+            .get_markets_by_pair(
+                TokenType::from_account_id(wnear).key(),
+                TokenType::from_account_id(usdc.clone()).key()
+            )
+            .len(),
+        1,
+        "migrated market not indexed"
+    );
+
+    // the market's tokens are registered, so deposits keep working
+    let info = contract
+        .get_token_info(TokenType::from_account_id(usdc.clone()).key())
+        .unwrap();
+    assert_eq!(info.status, TokenStatus::Allowed);
+    assert_eq!(info.deposit_cap, None);
+    set_predecessor_context(usdc.clone());
+    contract.ft_on_transfer(user.clone(), U128(50), String::new());
+    assert_eq!(get_balance(&contract, &user, usdc.clone().into()), 150);
+    set_predecessor_context(owner.clone());
+
+    // and written back in the current layout on save
+    let account = contract.internal_unwrap_account(&user);
+    contract.internal_save_account(&user, account);
+    assert!(matches!(
+        contract.accounts.get(&user),
+        Some(VAccount::Current(_))
+    ));
+    contract.internal_save_market(&market_id, market);
+    assert!(matches!(
+        contract.markets.get(&market_id),
+        Some(VMarket::Current(_))
+    ));
+
+    // migrating again is a no-op
+    env::state_write(&contract);
+    let contract = Contract::migrate();
+    assert_eq!(contract.prev_order_sequence_number, 42);
+    assert_eq!(get_balance(&contract, &user, usdc.into()), 150);
+}
+
+/// Accounts with resting orders from before locked balances were tracked get
+/// them rebuilt on read, and rewritten when their market is migrated. Placing
+/// orders waits until then.
+#[test]
+fn migrate_markets_rebuilds_locked_balances() {
+    let mut contract = setup_contract();
+    let (owner, maker, wnear, usdc) = get_accounts();
+
+    set_deposit_context(owner.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(
+        &mut contract,
+        CreateMarketArgs {
+            base_token: TokenType::from_account_id(wnear).key(),
+            base_token_lot_size: 1.into(),
+            quote_token: TokenType::from_account_id(usdc.clone()).key(),
+            quote_token_lot_size: 1.into(),
+            taker_fee_base_rate: 0,
+            maker_rebate_base_rate: 0,
+        },
+        0,
+        0,
+    );
+    storage_deposit(&mut contract, &maker);
+    contract.internal_deposit(&maker, &(&usdc).into(), 100);
+    set_predecessor_context(maker.clone());
+    contract.new_order(
+        market_id,
+        new_order_params(10, None, 3, Side::Buy, OrderType::Limit, None, None),
+    );
+
+    let storage_balance = contract.internal_unwrap_account(&maker).storage_balance;
+    rewrite_account_as_v1(&mut contract, &maker, storage_balance);
+    contract.market_migration_cursor = Some(0);
+    assert!(
+        std::panic::catch_unwind(|| contract.assert_markets_migrated()).is_err(),
+        "orders allowed before migration"
+    );
+    // locked balances are rebuilt on read, so cancels don't have to wait
+    contract.assert_can_cancel();
+    assert_eq!(
+        contract.get_locked_balances(&maker),
+        vec![(TokenType::from_account_id(usdc.clone()).key(), U128(30))]
+    );
+
+    set_predecessor_context(owner);
+    assert_eq!(contract.migrate_markets(10), 0);
+    assert!(matches!(
+        contract.accounts.get(&maker),
+        Some(VAccount::Current(_))
+    ));
+    assert_eq!(
+        contract.get_locked_balances(&maker),
+        vec![(TokenType::from_account_id(usdc).key(), U128(30))]
+    );
+    assert!(contract.check_locked_balances(maker.clone()));
+
+    set_predecessor_context(maker);
+    contract.cancel_all_orders(market_id);
+}
+
+/// Accounts saved before sizes were measured exactly can be short of the
+/// storage they use. They keep working, and fills against their resting
+/// orders settle even if the maker can't pay for the storage they add.
+#[test]
+fn v1_maker_short_of_storage_is_filled() {
+    let mut contract = setup_contract();
+    let (owner, maker, wnear, usdc) = get_accounts();
+    let taker = accounts(1);
+
+    set_deposit_context(owner.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(
+        &mut contract,
+        CreateMarketArgs {
+            base_token: TokenType::from_account_id(wnear.clone()).key(),
+            base_token_lot_size: 1.into(),
+            quote_token: TokenType::from_account_id(usdc.clone()).key(),
+            quote_token_lot_size: 1.into(),
+            taker_fee_base_rate: 0,
+            maker_rebate_base_rate: 0,
+        },
+        0,
+        0,
+    );
+    storage_deposit(&mut contract, &maker);
+    storage_deposit(&mut contract, &taker);
+    contract.internal_deposit(&maker, &(&usdc).into(), 100);
+    contract.internal_deposit(&taker, &(&wnear).into(), 10);
+    set_predecessor_context(maker.clone());
+    contract.new_order(
+        market_id,
+        new_order_params(10, None, 3, Side::Buy, OrderType::Limit, None, None),
+    );
+
+    rewrite_account_as_v1(&mut contract, &maker, 0);
+    contract.market_migration_cursor = Some(0);
+    set_predecessor_context(owner);
+    assert_eq!(contract.migrate_markets(10), 0);
+    let account = contract.internal_unwrap_account(&maker);
+    assert!(account.is_storage_covered(), "migrated account not covered");
+
+    set_predecessor_context(taker);
+    contract.new_order(
+        market_id,
+        new_order_params(10, None, 3, Side::Sell, OrderType::Limit, None, None),
+    );
+    assert_eq!(get_balance(&contract, &maker, (&wnear).into()), 3);
+    assert_eq!(get_balance(&contract, &maker, (&usdc).into()), 70);
+    assert!(contract.get_locked_balances(&maker).is_empty());
+    assert!(contract.check_locked_balances(maker));
+}
+
+/// Users can cancel orders and withdraw while the markets are migrated.
+#[test]
+fn cancel_and_withdraw_during_migration() {
+    let mut contract = setup_contract();
+    let (owner, maker, wnear, usdc) = get_accounts();
+    let token: TokenType = (&usdc).into();
+
+    set_deposit_context(owner, deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(
+        &mut contract,
+        CreateMarketArgs {
+            base_token: TokenType::from_account_id(wnear).key(),
+            base_token_lot_size: 1.into(),
+            quote_token: token.key(),
+            quote_token_lot_size: 1.into(),
+            taker_fee_base_rate: 0,
+            maker_rebate_base_rate: 0,
+        },
+        0,
+        0,
+    );
+    storage_deposit(&mut contract, &maker);
+    contract.internal_deposit(&maker, &token, 100);
+    set_predecessor_context(maker.clone());
+    contract.new_order(
+        market_id,
+        new_order_params(10, None, 3, Side::Buy, OrderType::Limit, None, None),
+    );
+
+    let storage_balance = contract.internal_unwrap_account(&maker).storage_balance;
+    rewrite_account_as_v1(&mut contract, &maker, storage_balance);
+    contract.market_migration_cursor = Some(0);
+
+    set_predecessor_context(maker.clone());
+    contract.cancel_all_orders(market_id);
+    assert_eq!(get_balance(&contract, &maker, token.clone()), 100);
+    assert!(contract.get_locked_balances(&maker).is_empty());
+
+    set_deposit_context(maker.clone(), 1);
+    contract.withdraw(token.key(), U128(100), None, None, None, None);
+    assert_eq!(get_balance(&contract, &maker, token), 0);
+}
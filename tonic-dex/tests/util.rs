@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::panic;
 
 use near_sdk::json_types::U128;
@@ -5,7 +6,10 @@ use tonic_dex::*;
 
 use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
 use near_sdk::test_utils::VMContextBuilder;
-use near_sdk::{testing_env, AccountId, Balance, Gas, PublicKey, VMContext};
+use near_sdk::{
+    testing_env, AccountId, Balance, Gas, PromiseResult, PublicKey, RuntimeFeesConfig, VMConfig,
+    VMContext,
+};
 
 fn max_account_id(c: &str) -> String {
     c.repeat(64)
@@ -89,6 +93,27 @@ pub fn set_predecessor_context(account_id: AccountId) {
     testing_env!(context);
 }
 
+pub fn set_timestamp_context(account_id: AccountId, block_timestamp: u64) {
+    let context = VMContextBuilder::new()
+        .predecessor_account_id(account_id)
+        .block_timestamp(block_timestamp)
+        .build();
+    testing_env!(context);
+}
+
+/// Call a private callback with the given results of the promises it follows.
+pub fn set_promise_results_context(results: Vec<PromiseResult>) {
+    let mut context = VMContextBuilder::new().build();
+    context.predecessor_account_id = context.current_account_id.clone();
+    testing_env!(
+        context,
+        VMConfig::test(),
+        RuntimeFeesConfig::test(),
+        Default::default(),
+        results
+    );
+}
+
 pub fn set_deposit_context(signer_id: AccountId, amount: Balance) {
     let context = VMContextBuilder::new()
         .predecessor_account_id(signer_id.clone())
@@ -133,12 +158,41 @@ pub fn get_mt_metadata(decimals: u8) -> MTBaseTokenMetadata {
     }
 }
 
+/// Args for a market with a base lot size of 1 and no fees.
+pub fn market_args(base: &AccountId, quote: &AccountId, quote_lot_size: u128) -> CreateMarketArgs {
+    CreateMarketArgs {
+        base_token: TokenType::from_account_id(base.clone()).key(),
+        base_token_lot_size: 1.into(),
+        quote_token: TokenType::from_account_id(quote.clone()).key(),
+        quote_token_lot_size: quote_lot_size.into(),
+        taker_fee_base_rate: 0,
+        maker_rebate_base_rate: 0,
+    }
+}
+
+/// Add the market's tokens to the token registry without cached decimals, so
+/// decimals are set through the metadata callbacks as before.
+pub fn register_market_tokens(contract: &mut Contract, args: &CreateMarketArgs) {
+    for token in [&args.base_token, &args.quote_token] {
+        contract.internal_register_token(
+            &TokenType::from_key(token),
+            TokenInfo::new(None, None, None),
+        );
+    }
+}
+
+/// Add a token to the token registry as allowed with no deposit cap.
+pub fn register_token(contract: &mut Contract, token: &TokenType) {
+    contract.internal_register_token(token, TokenInfo::new(None, None, None));
+}
+
 pub fn create_and_init_market(
     contract: &mut Contract,
     args: CreateMarketArgs,
     base_decimals: u8,
     quote_decimals: u8,
 ) -> MarketId {
+    register_market_tokens(contract, &args);
     let market_id = contract.create_market(args);
 
     contract.on_ft_metadata(
@@ -161,6 +215,7 @@ pub fn create_and_init_market_using_multitokens(
     base_decimals: u8,
     quote_decimals: u8,
 ) -> MarketId {
+    register_market_tokens(contract, &args);
     let market_id = contract.create_market(args);
 
     contract.on_mt_metadata(
@@ -245,7 +300,7 @@ pub fn assert_balance_invariant(
         // Add accumulated fees only when it's the quote token for the market
         if let Some(market) = market {
             if market.quote_token.token_type == TokenType::from(token_account.clone()) {
-                total += market.fees_accrued;
+                total += market.fees_accrued + market.creator_fees_accrued;
             }
         }
         assert!(
@@ -257,3 +312,25 @@ pub fn assert_balance_invariant(
         );
     }
 }
+
+/// Write an account back in the V1 layout, as if it was last saved before the
+/// contract was upgraded.
+pub fn rewrite_account_as_v1(
+    contract: &mut Contract,
+    account_id: &AccountId,
+    storage_balance: Balance,
+) {
+    let account = contract.internal_unwrap_account(account_id);
+    let mut open_orders = HashMap::new();
+    for market_id in account.open_order_market_ids() {
+        open_orders.insert(market_id, account.open_orders_iter(&market_id).collect());
+    }
+    contract.accounts.insert(
+        account_id,
+        &VAccount::V1(AccountV1 {
+            balances: account.get_balances().into_iter().collect(),
+            open_orders,
+            storage_balance,
+        }),
+    );
+}
@@ -11,19 +11,7 @@ fn get_order() {
     let (user_a, _, wnear, usdc) = get_accounts();
 
     set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
-    let market_id = create_and_init_market(
-        &mut contract,
-        CreateMarketArgs {
-            base_token: TokenType::from_account_id(wnear.clone()).key(),
-            base_token_lot_size: 1.into(),
-            quote_token: TokenType::from_account_id(usdc.clone()).key(),
-            quote_token_lot_size: 1.into(),
-            taker_fee_base_rate: 0,
-            maker_rebate_base_rate: 0,
-        },
-        0,
-        0,
-    );
+    let market_id = create_and_init_market(&mut contract, market_args(&wnear, &usdc, 1), 0, 0);
     contract.on_ft_metadata(market_id.into(), PairSide::Base, Some(get_ft_metadata(0)));
     contract.on_ft_metadata(market_id.into(), PairSide::Quote, Some(get_ft_metadata(0)));
 
@@ -57,3 +45,373 @@ fn get_order() {
         "timestamp not returned"
     );
 }
+
+#[test]
+fn get_depth() {
+    let mut contract = setup_contract();
+    let (user_a, user_b, wnear, usdc) = get_accounts();
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(&mut contract, market_args(&wnear, &usdc, 1), 0, 0);
+
+    for (user, price, quantity) in [(&user_a, 10, 5), (&user_b, 10, 3), (&user_a, 9, 2)] {
+        storage_deposit(&mut contract, user);
+        contract.internal_deposit(user, &(&usdc).into(), 100);
+        set_predecessor_context(user.clone());
+        contract.new_order(
+            market_id,
+            new_order_params(
+                price,
+                None,
+                quantity,
+                Side::Buy,
+                OrderType::Limit,
+                None,
+                None,
+            ),
+        );
+    }
+
+    let depth = contract.get_depth(market_id, 10, None).unwrap();
+    assert!(depth.asks.is_empty());
+    assert_eq!(depth.bids.len(), 2, "expected one entry per price");
+    assert_eq!(depth.bids[0].price, U128(10));
+    assert_eq!(depth.bids[0].total_quantity, U128(8));
+    assert_eq!(depth.bids[0].order_count, 2);
+    assert_eq!(depth.bids[0].cumulative_notional, U128(80));
+    assert_eq!(depth.bids[1].price, U128(9));
+    assert_eq!(depth.bids[1].cumulative_quantity, U128(10));
+    assert_eq!(depth.bids[1].cumulative_notional, U128(98));
+
+    let depth = contract.get_depth(market_id, 10, Some(U128(50))).unwrap();
+    assert_eq!(depth.bids.len(), 1, "notional limit not applied");
+}
+
+#[test]
+fn get_ticker() {
+    let mut contract = setup_contract();
+    let (user_a, user_b, wnear, usdc) = get_accounts();
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(&mut contract, market_args(&wnear, &usdc, 1), 0, 0);
+    for user in [&user_a, &user_b] {
+        storage_deposit(&mut contract, user);
+        contract.internal_deposit(user, &(&usdc).into(), 1000);
+        contract.internal_deposit(user, &(&wnear).into(), 1000);
+    }
+
+    set_predecessor_context(user_a.clone());
+    for (price, side) in [(90, Side::Buy), (100, Side::Buy), (110, Side::Sell)] {
+        contract.new_order(
+            market_id,
+            new_order_params(price, None, 5, side, OrderType::Limit, None, None),
+        );
+    }
+
+    let ticker = contract.get_ticker(market_id).unwrap();
+    assert_eq!(ticker.best_bid, Some(U128(100)));
+    assert_eq!(ticker.best_bid_size, Some(U128(5)));
+    assert_eq!(ticker.best_ask, Some(U128(110)));
+    assert_eq!(ticker.mid, Some(U128(105)));
+    assert_eq!(ticker.spread_bps, Some(952));
+    assert_eq!(ticker.last_price, None);
+
+    // sell 7 into the bids: 5 @ 100, 2 @ 90
+    set_predecessor_context(user_b);
+    contract.new_order(
+        market_id,
+        new_order_params(90, None, 7, Side::Sell, OrderType::Limit, None, None),
+    );
+
+    let tickers = contract.get_tickers(vec![market_id]);
+    let ticker = tickers[0].as_ref().unwrap();
+    assert_eq!(ticker.best_bid, Some(U128(90)));
+    assert_eq!(ticker.best_bid_size, Some(U128(3)));
+    assert_eq!(ticker.last_price, Some(U128(90)));
+    assert_eq!(ticker.last_quantity, Some(U128(2)));
+    assert_eq!(ticker.volume_24h, U128(7));
+    assert_eq!(ticker.quote_volume_24h, U128(680));
+    assert_eq!(ticker.high_24h, Some(U128(100)));
+    assert_eq!(ticker.low_24h, Some(U128(90)));
+}
+
+#[test]
+fn get_candles() {
+    let mut contract = setup_contract();
+    let (user_a, user_b, wnear, usdc) = get_accounts();
+    let one_second = 1_000_000_000;
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(&mut contract, market_args(&wnear, &usdc, 1), 0, 0);
+    storage_deposit(&mut contract, &user_a);
+    storage_deposit(&mut contract, &user_b);
+    contract.internal_deposit(&user_a, &(&usdc).into(), 1000);
+    contract.internal_deposit(&user_b, &(&wnear).into(), 1000);
+
+    for (timestamp, price, quantity) in [(0, 100, 5), (30, 90, 2), (61, 95, 1)] {
+        for (user, side) in [(&user_a, Side::Buy), (&user_b, Side::Sell)] {
+            set_timestamp_context(user.clone(), timestamp * one_second);
+            contract.new_order(
+                market_id,
+                new_order_params(price, None, quantity, side, OrderType::Limit, None, None),
+            );
+        }
+    }
+
+    let minutes = contract.get_candles(market_id, CandleResolution::OneMinute, U64(0), 10);
+    assert_eq!(minutes.len(), 2);
+    assert_eq!(minutes[0].start, U64(0));
+    assert_eq!(minutes[0].open, U128(100));
+    assert_eq!(minutes[0].low, U128(90));
+    assert_eq!(minutes[0].close, U128(90));
+    assert_eq!(minutes[0].volume, U128(7));
+    assert_eq!(minutes[1].start, U64(60 * one_second));
+    assert_eq!(minutes[1].close, U128(95));
+
+    let from_second_minute = contract.get_candles(
+        market_id,
+        CandleResolution::OneMinute,
+        U64(60 * one_second),
+        10,
+    );
+    assert_eq!(from_second_minute.len(), 1);
+
+    let hours = contract.get_candles(market_id, CandleResolution::OneHour, U64(0), 10);
+    assert_eq!(hours.len(), 1);
+    assert_eq!(hours[0].high, U128(100));
+    assert_eq!(hours[0].close, U128(95));
+    assert_eq!(hours[0].volume, U128(8));
+}
+
+#[test]
+fn get_twap_and_prices() {
+    let mut contract = setup_contract();
+    let (user_a, user_b, wnear, usdc) = get_accounts();
+    let one_second = 1_000_000_000;
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(&mut contract, market_args(&wnear, &usdc, 1), 0, 0);
+    storage_deposit(&mut contract, &user_a);
+    storage_deposit(&mut contract, &user_b);
+    contract.internal_deposit(&user_a, &(&usdc).into(), 10_000);
+    contract.internal_deposit(&user_b, &(&wnear).into(), 1000);
+    assert!(contract.get_twap(market_id, U64(one_second)).is_none());
+
+    // the spike and revert at 120s happen in one block and don't count
+    for (timestamp, price) in [(0, 100), (60, 200), (120, 1000), (120, 200)] {
+        for (user, side) in [(&user_a, Side::Buy), (&user_b, Side::Sell)] {
+            set_timestamp_context(user.clone(), timestamp * one_second);
+            contract.new_order(
+                market_id,
+                new_order_params(price, None, 1, side, OrderType::Limit, None, None),
+            );
+        }
+    }
+
+    set_timestamp_context(user_a.clone(), 180 * one_second);
+    let twap = contract.get_twap(market_id, U64(180 * one_second)).unwrap();
+    assert_eq!(twap.window, U64(180 * one_second));
+    // 100 for 60s, then 200 for 120s
+    assert_eq!(twap.price, U128(166));
+
+    let twap = contract.get_twap(market_id, U64(60 * one_second)).unwrap();
+    assert_eq!(twap.price, U128(200));
+
+    // longer than the available history
+    let twap = contract
+        .get_twap(market_id, U64(3600 * one_second))
+        .unwrap();
+    assert_eq!(twap.window, U64(180 * one_second));
+
+    let prices = contract.get_prices(vec![market_id]);
+    let price = prices[0].as_ref().unwrap();
+    assert_eq!(price.staleness, U64(60 * one_second));
+    assert_eq!(price.confidence, None, "book is empty");
+}
+
+#[test]
+fn get_twap_across_gap_without_trades() {
+    let mut contract = setup_contract();
+    let (user_a, user_b, wnear, usdc) = get_accounts();
+    let one_second = 1_000_000_000;
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(&mut contract, market_args(&wnear, &usdc, 1), 0, 0);
+    storage_deposit(&mut contract, &user_a);
+    storage_deposit(&mut contract, &user_b);
+    contract.internal_deposit(&user_a, &(&usdc).into(), 10_000);
+    contract.internal_deposit(&user_b, &(&wnear).into(), 1000);
+
+    // an hour without trades between the two prices
+    for (timestamp, price) in [(0, 100), (3600, 200)] {
+        for (user, side) in [(&user_a, Side::Buy), (&user_b, Side::Sell)] {
+            set_timestamp_context(user.clone(), timestamp * one_second);
+            contract.new_order(
+                market_id,
+                new_order_params(price, None, 1, side, OrderType::Limit, None, None),
+            );
+        }
+    }
+
+    set_timestamp_context(user_a.clone(), 3660 * one_second);
+    let twap = contract.get_twap(market_id, U64(300 * one_second)).unwrap();
+    assert_eq!(twap.window, U64(300 * one_second));
+    // 100 for 240s, then 200 for 60s
+    assert_eq!(twap.price, U128(120));
+
+    // no trades at all in the window
+    set_timestamp_context(user_a.clone(), 7200 * one_second);
+    let twap = contract.get_twap(market_id, U64(300 * one_second)).unwrap();
+    assert_eq!(twap.window, U64(300 * one_second));
+    assert_eq!(twap.price, U128(200));
+}
+
+#[test]
+fn top_of_book_moves_advance_twap() {
+    let mut contract = setup_contract();
+    let (user_a, user_b, wnear, usdc) = get_accounts();
+    let one_second = 1_000_000_000;
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(&mut contract, market_args(&wnear, &usdc, 1), 0, 0);
+    storage_deposit(&mut contract, &user_a);
+    storage_deposit(&mut contract, &user_b);
+    contract.internal_deposit(&user_a, &(&usdc).into(), 10_000);
+    contract.internal_deposit(&user_b, &(&wnear).into(), 1000);
+    for (user, side) in [(&user_a, Side::Buy), (&user_b, Side::Sell)] {
+        set_timestamp_context(user.clone(), 0);
+        contract.new_order(
+            market_id,
+            new_order_params(100, None, 1, side, OrderType::Limit, None, None),
+        );
+    }
+
+    // a resting bid moves the top of book without trading
+    set_timestamp_context(user_a.clone(), 3600 * one_second);
+    contract.new_order(
+        market_id,
+        new_order_params(90, None, 1, Side::Buy, OrderType::Limit, None, None),
+    );
+    let oracle = contract.oracles.get(&market_id).unwrap();
+    assert_eq!(oracle.observation_count, 2);
+    assert_eq!(oracle.last_update, 3600 * one_second);
+    assert_eq!(oracle.last_trade, 0);
+
+    set_timestamp_context(user_a.clone(), 3660 * one_second);
+    let twap = contract.get_twap(market_id, U64(300 * one_second)).unwrap();
+    assert_eq!(twap.price, U128(100));
+    let prices = contract.get_prices(vec![market_id]);
+    let price = prices[0].as_ref().unwrap();
+    assert_eq!(price.staleness, U64(3660 * one_second));
+}
+
+#[test]
+fn get_orderbook_snapshot() {
+    let mut contract = setup_contract();
+    let (user_a, user_b, wnear, usdc) = get_accounts();
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(&mut contract, market_args(&wnear, &usdc, 1), 0, 0);
+
+    for (user, price, quantity) in [(&user_a, 9, 2), (&user_a, 10, 5), (&user_b, 10, 3)] {
+        storage_deposit(&mut contract, user);
+        contract.internal_deposit(user, &(&usdc).into(), 100);
+        set_predecessor_context(user.clone());
+        contract.new_order(
+            market_id,
+            new_order_params(
+                price,
+                None,
+                quantity,
+                Side::Buy,
+                OrderType::Limit,
+                None,
+                None,
+            ),
+        );
+    }
+
+    let page = contract
+        .get_orderbook_snapshot(market_id, Side::Buy, None, 2)
+        .unwrap();
+    assert_eq!(
+        page.prev_order_sequence_number,
+        U64(contract.prev_order_sequence_number)
+    );
+    assert!(page.has_more);
+    assert_eq!(page.orders.len(), 2);
+    assert_eq!(page.orders[0].owner, user_a);
+    assert_eq!(page.orders[0].limit_price, U128(10));
+    assert_eq!(page.orders[0].open_qty, U128(5));
+    assert_eq!(page.orders[0].original_qty, Some(U128(5)));
+    assert_eq!(page.orders[1].owner, user_b);
+    assert_eq!(page.orders[1].original_qty, Some(U128(3)));
+
+    let page = contract
+        .get_orderbook_snapshot(market_id, Side::Buy, Some(page.orders[1].order_id), 2)
+        .unwrap();
+    assert!(!page.has_more);
+    assert_eq!(page.orders.len(), 1);
+    assert_eq!(page.orders[0].limit_price, U128(9));
+    assert_eq!(page.orders[0].original_qty, Some(U128(2)));
+
+    let asks = contract
+        .get_orderbook_snapshot(market_id, Side::Sell, None, 2)
+        .unwrap();
+    assert!(asks.orders.is_empty());
+}
+
+#[test]
+fn get_account() {
+    let mut contract = setup_contract();
+    let (user_a, user_b, wnear, usdc) = get_accounts();
+    assert!(contract.get_account(user_b).is_none());
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(&mut contract, market_args(&wnear, &usdc, 1), 0, 0);
+    storage_deposit(&mut contract, &user_a);
+    contract.internal_deposit(&user_a, &(&usdc).into(), 100);
+    set_predecessor_context(user_a.clone());
+    contract.new_order(
+        market_id,
+        new_order_params(10, None, 3, Side::Buy, OrderType::Limit, None, None),
+    );
+
+    let usdc_key = TokenType::from_account_id(usdc).key();
+    let account = contract.get_account(user_a).unwrap();
+    assert_eq!(account.balances, vec![(usdc_key.clone(), U128(70))]);
+    assert_eq!(account.locked_balances, vec![(usdc_key, U128(30))]);
+    assert_eq!(account.open_orders.len(), 1);
+    assert_eq!(account.open_orders[0].market_id, market_id);
+    assert_eq!(account.open_orders[0].orders[0].original_qty, Some(U128(3)));
+    assert!(account.storage_usage.0 > 0);
+    assert_eq!(account.fee_tier, FeeTier::Base);
+}
+
+#[test]
+fn get_liabilities() {
+    let mut contract = setup_contract();
+    let (user_a, _, wnear, usdc) = get_accounts();
+    let usdc_key = TokenType::from_account_id(usdc.clone()).key();
+    assert_eq!(contract.get_liabilities(usdc_key.clone()).total, U128(0));
+
+    set_deposit_context(user_a.clone(), deposits::TENTH_NEAR);
+    let market_id = create_and_init_market(&mut contract, market_args(&wnear, &usdc, 1), 0, 0);
+    storage_deposit(&mut contract, &user_a);
+    contract.internal_deposit(&user_a, &(&usdc).into(), 100);
+    set_predecessor_context(user_a.clone());
+    let order = contract.new_order(
+        market_id,
+        new_order_params(10, None, 3, Side::Buy, OrderType::Limit, None, None),
+    );
+
+    let liabilities = contract.get_liabilities(usdc_key.clone());
+    assert_eq!(liabilities.balances, U128(70));
+    assert_eq!(liabilities.locked, U128(30));
+    assert_eq!(liabilities.total, U128(100));
+
+    contract.cancel_order(market_id, order.id);
+    let liabilities = contract.get_liabilities(usdc_key);
+    assert_eq!(liabilities.balances, U128(100));
+    assert_eq!(liabilities.locked, U128(0));
+}
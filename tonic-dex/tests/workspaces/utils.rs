@@ -27,6 +27,8 @@ pub async fn init_dex_and_deposit(
     let (alice, bob, dex) = init_dex(worker).await?;
 
     let (qt_contract, bt_contract) = deploy_test_tokens(&worker).await?;
+    register_token(&worker, &alice, &dex, &bt_contract).await?;
+    register_token(&worker, &alice, &dex, &qt_contract).await?;
     token_storage_deposit(&worker, &alice, &dex.as_account(), &bt_contract).await?;
     token_storage_deposit(&worker, &alice, &dex.as_account(), &qt_contract).await?;
     dex_storage_deposit(&worker, &alice, &dex).await?;
@@ -84,6 +86,24 @@ pub async fn token_storage_deposit(
     Ok(true)
 }
 
+/// Add a token to the DEX token registry. Caller must be the DEX owner.
+pub async fn register_token(
+    worker: &Worker<impl DevNetwork>,
+    owner: &Account,
+    dex_contract: &Contract,
+    token_contract: &Contract,
+) -> anyhow::Result<bool> {
+    let res = owner
+        .call(&worker, dex_contract.id().clone(), "register_token")
+        .args_json(json!({
+            "token": format!("ft:{}", token_contract.id()),
+        }))?
+        .transact()
+        .await?;
+    assert!(matches!(res.status, FinalExecutionStatus::SuccessValue(_)));
+    Ok(true)
+}
+
 pub async fn dex_storage_deposit(
     worker: &Worker<impl DevNetwork>,
     caller: &Account,